@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{RcAuthError, Result};
+use crate::session::Session;
+use crate::yggdrasil::YggdrasilSession;
+
+/// Maximum length of a Minecraft player name
+const MAX_NAME_LEN: usize = 16;
+
+/// Kind of account, as passed to the game with `--userType`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserType {
+    /// Microsoft account
+    Msa,
+    /// Third-party Yggdrasil (authlib-injector) account
+    Mojang,
+    /// Offline (non-premium) account
+    Local,
+}
+
+impl UserType {
+    /// Value for the game's `--userType` argument
+    pub fn as_launch_arg(&self) -> &'static str {
+        match self {
+            Self::Msa => "msa",
+            Self::Mojang => "mojang",
+            Self::Local => "legacy",
+        }
+    }
+}
+
+/// Stored account: a Microsoft session, a Yggdrasil session or an offline player
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Account {
+    Microsoft(Box<Session>),
+    Yggdrasil(Box<YggdrasilSession>),
+    Offline(OfflineAccount),
+}
+
+impl Account {
+    /// Get the account key (UUID without dashes) for storage
+    pub fn account_key(&self) -> &str {
+        match self {
+            Self::Microsoft(session) => session.account_key(),
+            Self::Yggdrasil(session) => session.account_key(),
+            Self::Offline(offline) => offline.account_key(),
+        }
+    }
+
+    /// Player name
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Microsoft(session) => &session.profile.name,
+            Self::Yggdrasil(session) => &session.profile.name,
+            Self::Offline(offline) => &offline.name,
+        }
+    }
+
+    pub fn user_type(&self) -> UserType {
+        match self {
+            Self::Microsoft(_) => UserType::Msa,
+            Self::Yggdrasil(_) => UserType::Mojang,
+            Self::Offline(_) => UserType::Local,
+        }
+    }
+
+    /// Minecraft access token, if the account has one
+    pub fn access_token(&self) -> Option<&str> {
+        match self {
+            Self::Microsoft(session) => Some(&session.mc.access_token),
+            Self::Yggdrasil(session) => Some(&session.access_token),
+            Self::Offline(_) => None,
+        }
+    }
+
+    /// Check if the account has tokens that need refresh
+    pub fn needs_refresh(&self) -> bool {
+        match self {
+            Self::Microsoft(session) => session.needs_refresh(),
+            Self::Yggdrasil(_) | Self::Offline(_) => false,
+        }
+    }
+
+    pub fn as_session(&self) -> Option<&Session> {
+        match self {
+            Self::Microsoft(session) => Some(session),
+            Self::Yggdrasil(_) | Self::Offline(_) => None,
+        }
+    }
+
+    pub fn into_session(self) -> Option<Session> {
+        match self {
+            Self::Microsoft(session) => Some(*session),
+            Self::Yggdrasil(_) | Self::Offline(_) => None,
+        }
+    }
+}
+
+impl From<Session> for Account {
+    fn from(session: Session) -> Self {
+        Self::Microsoft(Box::new(session))
+    }
+}
+
+impl From<YggdrasilSession> for Account {
+    fn from(session: YggdrasilSession) -> Self {
+        Self::Yggdrasil(Box::new(session))
+    }
+}
+
+impl From<OfflineAccount> for Account {
+    fn from(offline: OfflineAccount) -> Self {
+        Self::Offline(offline)
+    }
+}
+
+/// Offline (non-premium) account
+///
+/// Uses the same `OfflinePlayer:<name>` UUIDv3 as the vanilla server, so the
+/// player keeps their identity on offline-mode servers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OfflineAccount {
+    pub name: String,
+    /// UUID without dashes
+    pub uuid: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OfflineAccount {
+    /// Create an offline account for a player name
+    ///
+    /// Names must be 1-16 characters of `A-Z`, `a-z`, `0-9` or `_`.
+    pub fn new(name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+        validate_player_name(&name)?;
+
+        Ok(Self {
+            uuid: offline_uuid(&name),
+            name,
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Get the account key (UUID) for storage
+    pub fn account_key(&self) -> &str {
+        &self.uuid
+    }
+}
+
+/// Check that a player name is 1-16 characters of `A-Z`, `a-z`, `0-9` or `_`
+pub(crate) fn validate_player_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(RcAuthError::InvalidPlayerName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Derive the offline UUID for a player name (UUIDv3 of `OfflinePlayer:<name>`)
+///
+/// Matches Java's `UUID.nameUUIDFromBytes`, returned without dashes.
+pub fn offline_uuid(name: &str) -> String {
+    let mut bytes: [u8; 16] = Md5::digest(format!("OfflinePlayer:{}", name).as_bytes()).into();
+
+    // Set version 3 and the IETF variant
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_uuid_matches_vanilla() {
+        // Values from UUID.nameUUIDFromBytes("OfflinePlayer:<name>")
+        assert_eq!(offline_uuid("Notch"), "b50ad385829d3141a2167e7d7539ba7f");
+        assert_eq!(offline_uuid("jeb_"), "a762f5604fce3236812ab80efff0b62b");
+    }
+
+    #[test]
+    fn test_offline_account_validation() {
+        assert!(OfflineAccount::new("Steve_123").is_ok());
+        assert!(matches!(
+            OfflineAccount::new(""),
+            Err(RcAuthError::InvalidPlayerName(_))
+        ));
+        assert!(matches!(
+            OfflineAccount::new("ThisNameIsWayTooLong"),
+            Err(RcAuthError::InvalidPlayerName(_))
+        ));
+        assert!(matches!(
+            OfflineAccount::new("bad name"),
+            Err(RcAuthError::InvalidPlayerName(_))
+        ));
+    }
+
+    #[test]
+    fn test_account_serde_roundtrip() {
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+
+        let json = serde_json::to_string(&account).unwrap();
+        assert!(json.contains(r#""kind":"offline""#));
+
+        let decoded: Account = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, account);
+        assert_eq!(decoded.user_type(), UserType::Local);
+        assert_eq!(decoded.account_key(), offline_uuid("Steve"));
+    }
+}
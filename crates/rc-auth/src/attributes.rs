@@ -0,0 +1,152 @@
+use tracing::{debug, instrument};
+
+use crate::client::RcAuthClient;
+use crate::errors::{RcAuthError, Result};
+use crate::models::{PlayerAttributes, PlayerAttributesUpdate};
+
+impl RcAuthClient {
+    /// Fetch the player's privileges and preferences
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn fetch_player_attributes(&self, mc_access_token: &str) -> Result<PlayerAttributes> {
+        debug!("Fetching player attributes");
+        let response = self
+            .send(|| {
+                self.http
+                    .get(self.config.endpoints.player_attributes())
+                    .bearer_auth(mc_access_token)
+            })
+            .await?;
+
+        read_attributes(response).await
+    }
+
+    /// Update the player's preferences and return the resulting attributes
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn update_player_attributes(
+        &self,
+        mc_access_token: &str,
+        update: &PlayerAttributesUpdate,
+    ) -> Result<PlayerAttributes> {
+        debug!("Updating player attributes");
+        let response = self
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.player_attributes())
+                    .bearer_auth(mc_access_token)
+                    .json(update)
+            })
+            .await?;
+
+        read_attributes(response).await
+    }
+}
+
+async fn read_attributes(response: reqwest::Response) -> Result<PlayerAttributes> {
+    let status = response.status();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(RcAuthError::Http {
+            status,
+            body_snippet: body.chars().take(200).collect(),
+        });
+    }
+
+    Ok(response.json().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::mock_client;
+    use crate::models::{Privilege, ProfanityFilterPreferences};
+    use serde_json::json;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn attributes_body(multiplayer: bool, profanity_filter_on: bool) -> serde_json::Value {
+        json!({
+            "privileges": {
+                "onlineChat": { "enabled": multiplayer },
+                "multiplayerServer": { "enabled": multiplayer },
+                "multiplayerRealms": { "enabled": multiplayer },
+                "telemetry": { "enabled": true },
+                "optionalTelemetry": { "enabled": false }
+            },
+            "profanityFilterPreferences": { "profanityFilterOn": profanity_filter_on },
+            "banStatus": { "bannedScopes": {} }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_child_account_privileges() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("GET"))
+            .and(path("/player/attributes"))
+            .and(header("Authorization", "Bearer mc-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(attributes_body(false, true)))
+            .mount(&server)
+            .await;
+
+        let attributes = client.fetch_player_attributes("mc-token").await.unwrap();
+        let disabled = Some(Privilege { enabled: false });
+        assert_eq!(attributes.privileges.multiplayer_server, disabled);
+        assert_eq!(attributes.privileges.online_chat, disabled);
+        assert_eq!(
+            attributes.privileges.telemetry,
+            Some(Privilege { enabled: true })
+        );
+        assert!(attributes.profanity_filter_preferences.profanity_filter_on);
+    }
+
+    #[tokio::test]
+    async fn test_toggle_profanity_filter() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/player/attributes"))
+            .and(body_json(json!({
+                "profanityFilterPreferences": { "profanityFilterOn": false }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(attributes_body(true, false)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let update = PlayerAttributesUpdate {
+            profanity_filter_preferences: Some(ProfanityFilterPreferences {
+                profanity_filter_on: false,
+            }),
+        };
+        let attributes = client
+            .update_player_attributes("mc-token", &update)
+            .await
+            .unwrap();
+        assert!(!attributes.profanity_filter_preferences.profanity_filter_on);
+        assert_eq!(
+            attributes.privileges.multiplayer_realms,
+            Some(Privilege { enabled: true })
+        );
+    }
+
+    #[test]
+    fn test_missing_privileges_are_unknown() {
+        let attributes: PlayerAttributes = serde_json::from_value(json!({
+            "privileges": {
+                "multiplayerServer": { "enabled": true }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            attributes.privileges.multiplayer_server,
+            Some(Privilege { enabled: true })
+        );
+        assert_eq!(attributes.privileges.online_chat, None);
+        assert_eq!(attributes.privileges.multiplayer_realms, None);
+        assert_eq!(attributes.privileges.telemetry, None);
+    }
+}
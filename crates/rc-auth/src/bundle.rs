@@ -0,0 +1,110 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::account::Account;
+use crate::crypto::{self, EncryptedBlob};
+use crate::errors::{RcAuthError, Result};
+use crate::key_manager::{KdfParams, derive_key, generate_salt};
+
+/// Value of the `format` field identifying an account bundle
+const BUNDLE_FORMAT: &str = "rc-auth-bundle";
+
+/// Current bundle version
+const BUNDLE_VERSION: u32 = 1;
+
+/// Context bound into the bundle's AAD in place of an account key
+const BUNDLE_AAD_CONTEXT: &str = "bundle";
+
+/// Portable, passphrase-protected set of accounts
+///
+/// The accounts are serialized together and encrypted with AES-256-GCM under a
+/// key derived from the passphrase with Argon2id, so the bundle doesn't depend
+/// on the keyring or passphrase of the store it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountBundle {
+    pub format: String,
+    pub version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Base64-encoded Argon2id salt
+    pub salt: String,
+    #[serde(default)]
+    pub kdf: KdfParams,
+    pub data: EncryptedBlob,
+}
+
+impl AccountBundle {
+    /// Encrypt accounts into a bundle
+    pub fn seal(accounts: &[Account], passphrase: &str) -> Result<Self> {
+        let kdf = KdfParams::default();
+        let salt = generate_salt()?;
+        let key = derive_key(passphrase, &salt, &kdf)?;
+
+        let plaintext = Zeroizing::new(serde_json::to_vec(accounts)?);
+        let data = crypto::encrypt(&key, &plaintext, BUNDLE_AAD_CONTEXT)?;
+
+        Ok(Self {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            created_at: chrono::Utc::now(),
+            salt: STANDARD.encode(&salt),
+            kdf,
+            data,
+        })
+    }
+
+    /// Decrypt the accounts in a bundle
+    ///
+    /// Returns `RcAuthError::WrongPassphrase` if the passphrase doesn't match.
+    pub fn open(&self, passphrase: &str) -> Result<Vec<Account>> {
+        if self.format != BUNDLE_FORMAT {
+            return Err(RcAuthError::InvalidResponse(
+                "Not an account bundle".to_string(),
+            ));
+        }
+        if self.version > BUNDLE_VERSION {
+            return Err(RcAuthError::UnsupportedStoreVersion {
+                found: self.version,
+                supported: BUNDLE_VERSION,
+            });
+        }
+
+        let salt = STANDARD
+            .decode(&self.salt)
+            .map_err(|_| RcAuthError::CorruptedStore)?;
+        let key = derive_key(passphrase, &salt, &self.kdf)?;
+
+        let plaintext = match crypto::decrypt(&key, &self.data, BUNDLE_AAD_CONTEXT) {
+            Ok(plaintext) => Zeroizing::new(plaintext),
+            Err(RcAuthError::CorruptedStore) => return Err(RcAuthError::WrongPassphrase),
+            Err(e) => return Err(e),
+        };
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::OfflineAccount;
+
+    #[test]
+    fn test_seal_and_open() {
+        let accounts = vec![
+            Account::from(OfflineAccount::new("Steve").unwrap()),
+            Account::from(OfflineAccount::new("Alex").unwrap()),
+        ];
+
+        let bundle = AccountBundle::seal(&accounts, "correct horse").unwrap();
+        let json = serde_json::to_string(&bundle).unwrap();
+        assert!(!json.contains("Steve"));
+
+        let bundle: AccountBundle = serde_json::from_str(&json).unwrap();
+        assert_eq!(bundle.open("correct horse").unwrap(), accounts);
+        assert!(matches!(
+            bundle.open("battery staple"),
+            Err(RcAuthError::WrongPassphrase)
+        ));
+    }
+}
@@ -3,35 +3,76 @@ use tracing::{debug, instrument, warn};
 use url::Url;
 
 use crate::config::{
-    AuthorizeFlavor, RP_MINECRAFT, RP_XBOXLIVE, RcAuthConfig, STANDARD_SCOPE, endpoints, official,
+    AuthorizeFlavor, DEVICE_CODE_GRANT_TYPE, DEVICE_CODE_SLOW_DOWN_STEP, RP_MINECRAFT, RP_XBOXLIVE,
+    RcAuthConfig, STANDARD_SCOPE, official,
 };
-use crate::errors::{RcAuthError, Result, XstsError};
+use crate::errors::{OAuthError, RcAuthError, Result, XstsError};
 use crate::models::*;
+use crate::pkce::{self, LoginAttempt};
+use crate::retry::{self, Idempotency};
 use crate::session::{McToken, MsTokens, Session, XblToken, XstsToken};
 
+/// Build the HTTP client shared by all rc-auth service clients
+pub(crate) fn build_http_client(config: &RcAuthConfig) -> Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(config.http_timeouts.connect)
+        .timeout(config.http_timeouts.request)
+        .user_agent(config.user_agent.as_deref().unwrap_or("rauncher-mc"))
+        .build()?)
+}
+
 /// Main client for Microsoft authentication
 #[derive(Debug, Clone)]
 pub struct RcAuthClient {
-    config: RcAuthConfig,
-    http: Client,
+    pub(crate) config: RcAuthConfig,
+    pub(crate) http: Client,
 }
 
 impl RcAuthClient {
     /// Create a new authentication client
     pub fn new(config: RcAuthConfig) -> Result<Self> {
-        let http = Client::builder()
-            .connect_timeout(config.http_timeouts.connect)
-            .timeout(config.http_timeouts.request)
-            .user_agent(config.user_agent.as_deref().unwrap_or("rauncher-mc"))
-            .build()?;
+        let http = build_http_client(&config)?;
 
         Ok(Self { config, http })
     }
 
+    /// Send a request built by `build`, retrying transient failures
+    pub(crate) async fn send<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        retry::send_with_retry(&self.config.retry, Idempotency::Idempotent, build).await
+    }
+
+    /// Send a request that spends a single-use code or token, without retrying timeouts
+    pub(crate) async fn send_single_use<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        retry::send_with_retry(&self.config.retry, Idempotency::SingleUse, build).await
+    }
+
+    /// OAuth scope for the configured flavor
+    fn scope(&self) -> &'static str {
+        match &self.config.authorize_flavor {
+            AuthorizeFlavor::OfficialDesktop => official::SCOPE,
+            AuthorizeFlavor::StandardCode => STANDARD_SCOPE,
+        }
+    }
+
     /// Build the authorization URL for the user to visit
+    ///
+    /// Generates a PKCE code verifier (S256) and, unless one is given, a random
+    /// `state`. The returned [`LoginAttempt`] must be passed to
+    /// [`RcAuthClient::exchange_code`] once the redirect comes back.
     #[instrument(skip(self))]
-    pub fn build_authorize_url(&self, state: Option<String>) -> Result<Url> {
-        let mut url = Url::parse(endpoints::MS_AUTHORIZE)?;
+    pub fn build_authorize_url(&self, state: Option<String>) -> Result<LoginAttempt> {
+        let mut url = Url::parse(&self.config.endpoints.ms_authorize())?;
+        let state = match state {
+            Some(s) => s,
+            None => pkce::generate_state()?,
+        };
+        let code_verifier = pkce::generate_code_verifier()?;
 
         match &self.config.authorize_flavor {
             AuthorizeFlavor::OfficialDesktop => {
@@ -45,10 +86,6 @@ impl RcAuthClient {
                 for (key, value) in official::EXTRA_PARAMS {
                     url.query_pairs_mut().append_pair(key, value);
                 }
-
-                if let Some(s) = state {
-                    url.query_pairs_mut().append_pair("state", &s);
-                }
             }
             AuthorizeFlavor::StandardCode => {
                 url.query_pairs_mut()
@@ -57,15 +94,16 @@ impl RcAuthClient {
                     .append_pair("redirect_uri", self.config.redirect_uri.as_str())
                     .append_pair("scope", STANDARD_SCOPE)
                     .append_pair("prompt", "select_account");
-
-                if let Some(s) = state {
-                    url.query_pairs_mut().append_pair("state", &s);
-                }
             }
         }
 
+        url.query_pairs_mut()
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &pkce::code_challenge(&code_verifier))
+            .append_pair("code_challenge_method", pkce::CODE_CHALLENGE_METHOD);
+
         debug!("Built authorize URL: {}", url);
-        Ok(url)
+        Ok(LoginAttempt::new(url, state, code_verifier))
     }
 
     /// Parse the redirect URL and extract the authorization code
@@ -98,34 +136,66 @@ impl RcAuthClient {
             .ok_or(RcAuthError::InvalidRedirect)
     }
 
-    /// Exchange authorization code for Microsoft tokens
-    #[instrument(skip(self, code))]
-    pub async fn exchange_code(&self, code: &str) -> Result<MsTokens> {
-        let scope = match &self.config.authorize_flavor {
-            AuthorizeFlavor::OfficialDesktop => official::SCOPE,
-            AuthorizeFlavor::StandardCode => STANDARD_SCOPE,
-        };
+    /// Request a device code for the device code login flow
+    ///
+    /// Show `verification_uri` and `user_code` to the user, then call
+    /// [`RcAuthClient::wait_for_device_code`] or
+    /// [`RcAuthClient::complete_login_with_device_code`].
+    #[instrument(skip(self))]
+    pub async fn request_device_code(&self) -> Result<DeviceCode> {
+        debug!("Requesting device code");
+        let response = self
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.ms_device_code())
+                    .form(&[
+                        ("client_id", self.config.client_id.as_str()),
+                        ("scope", self.scope()),
+                        ("response_type", "device_code"),
+                    ])
+            })
+            .await?;
 
-        debug!("Exchanging authorization code for tokens");
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(oauth_error(status, &body));
+        }
+
+        let device_response: DeviceCodeResponse = response.json().await?;
+        Ok(device_response.into())
+    }
+
+    /// Poll the token endpoint once for a pending device code
+    ///
+    /// Returns [`RcAuthError::AuthorizationPending`] while the user hasn't finished
+    /// and [`RcAuthError::SlowDown`] when the polling interval must be increased.
+    #[instrument(skip(self, device))]
+    pub async fn poll_device_token(&self, device: &DeviceCode) -> Result<MsTokens> {
         let response = self
-            .http
-            .post(endpoints::MS_TOKEN)
-            .form(&[
-                ("client_id", self.config.client_id.as_str()),
-                ("code", code),
-                ("redirect_uri", self.config.redirect_uri.as_str()),
-                ("grant_type", "authorization_code"),
-                ("scope", scope),
-            ])
-            .send()
+            .send_single_use(|| {
+                self.http.post(self.config.endpoints.ms_token()).form(&[
+                    ("client_id", self.config.client_id.as_str()),
+                    ("device_code", device.device_code.as_str()),
+                    ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                ])
+            })
             .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
 
-            if body.contains("invalid_grant") {
-                return Err(RcAuthError::OAuthInvalidGrant);
+            if let Ok(error) = serde_json::from_str::<MsOAuthErrorResponse>(&body) {
+                match error.error.as_str() {
+                    "authorization_pending" => return Err(RcAuthError::AuthorizationPending),
+                    "slow_down" => return Err(RcAuthError::SlowDown),
+                    "expired_token" => return Err(RcAuthError::DeviceCodeExpired),
+                    "authorization_declined" | "access_denied" => {
+                        return Err(RcAuthError::AuthorizationDeclined);
+                    }
+                    _ => return Err(OAuthError::from(error).into()),
+                }
             }
 
             return Err(RcAuthError::Http {
@@ -142,39 +212,87 @@ impl RcAuthClient {
         ))
     }
 
+    /// Poll until the user completes the device code authorization
+    ///
+    /// Honors the server-provided interval, backs off on `slow_down` and gives up
+    /// with [`RcAuthError::DeviceCodeExpired`] once the code expires.
+    #[instrument(skip(self, device))]
+    pub async fn wait_for_device_code(&self, device: &DeviceCode) -> Result<MsTokens> {
+        let mut interval = device.interval;
+
+        loop {
+            if device.is_expired() {
+                return Err(RcAuthError::DeviceCodeExpired);
+            }
+
+            tokio::time::sleep(interval).await;
+
+            match self.poll_device_token(device).await {
+                Ok(tokens) => return Ok(tokens),
+                Err(RcAuthError::AuthorizationPending) => {
+                    debug!("Device code authorization still pending");
+                }
+                Err(RcAuthError::SlowDown) => {
+                    interval += DEVICE_CODE_SLOW_DOWN_STEP;
+                    debug!("Device code polling slowed down to {:?}", interval);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Exchange authorization code for Microsoft tokens
+    #[instrument(skip(self, code, attempt))]
+    pub async fn exchange_code(&self, code: &str, attempt: &LoginAttempt) -> Result<MsTokens> {
+        debug!("Exchanging authorization code for tokens");
+        let response = self
+            .send_single_use(|| {
+                self.http.post(self.config.endpoints.ms_token()).form(&[
+                    ("client_id", self.config.client_id.as_str()),
+                    ("code", code),
+                    ("redirect_uri", self.config.redirect_uri.as_str()),
+                    ("grant_type", "authorization_code"),
+                    ("scope", self.scope()),
+                    ("code_verifier", attempt.code_verifier()),
+                ])
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+
+            return Err(oauth_error(status, &body));
+        }
+
+        let token_response: MsTokenResponse = response.json().await?;
+        Ok(MsTokens::new(
+            token_response.access_token,
+            token_response.refresh_token,
+            token_response.expires_in,
+        ))
+    }
+
     /// Refresh Microsoft tokens using refresh_token
     #[instrument(skip(self, refresh_token))]
     pub async fn refresh_ms_token(&self, refresh_token: &str) -> Result<MsTokens> {
-        let scope = match &self.config.authorize_flavor {
-            AuthorizeFlavor::OfficialDesktop => official::SCOPE,
-            AuthorizeFlavor::StandardCode => STANDARD_SCOPE,
-        };
-
         debug!("Refreshing Microsoft access token");
         let response = self
-            .http
-            .post(endpoints::MS_TOKEN)
-            .form(&[
-                ("client_id", self.config.client_id.as_str()),
-                ("refresh_token", refresh_token),
-                ("grant_type", "refresh_token"),
-                ("scope", scope),
-            ])
-            .send()
+            .send_single_use(|| {
+                self.http.post(self.config.endpoints.ms_token()).form(&[
+                    ("client_id", self.config.client_id.as_str()),
+                    ("refresh_token", refresh_token),
+                    ("grant_type", "refresh_token"),
+                    ("scope", self.scope()),
+                ])
+            })
             .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
 
-            if body.contains("invalid_grant") {
-                return Err(RcAuthError::OAuthInvalidGrant);
-            }
-
-            return Err(RcAuthError::Http {
-                status,
-                body_snippet: body.chars().take(200).collect(),
-            });
+            return Err(oauth_error(status, &body));
         }
 
         let token_response: MsTokenResponse = response.json().await?;
@@ -200,12 +318,13 @@ impl RcAuthClient {
 
         debug!("Authenticating with Xbox Live");
         let response = self
-            .http
-            .post(endpoints::XBL_AUTHENTICATE)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.xbl_authenticate())
+                    .header("Accept", "application/json")
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
             .await?;
 
         // Handle the "d=" retry caveat
@@ -223,12 +342,13 @@ impl RcAuthClient {
             };
 
             let retry_response = self
-                .http
-                .post(endpoints::XBL_AUTHENTICATE)
-                .header("Accept", "application/json")
-                .header("Content-Type", "application/json")
-                .json(&retry_request)
-                .send()
+                .send(|| {
+                    self.http
+                        .post(self.config.endpoints.xbl_authenticate())
+                        .header("Accept", "application/json")
+                        .header("Content-Type", "application/json")
+                        .json(&retry_request)
+                })
                 .await?;
 
             if !retry_response.status().is_success() {
@@ -291,12 +411,13 @@ impl RcAuthClient {
 
         debug!("Authorizing with XSTS");
         let response = self
-            .http
-            .post(endpoints::XSTS_AUTHORIZE)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.xsts_authorize())
+                    .header("Accept", "application/json")
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
             .await?;
 
         if response.status() == StatusCode::UNAUTHORIZED {
@@ -348,12 +469,13 @@ impl RcAuthClient {
 
         debug!("Fetching XUID and gamertag");
         let response = self
-            .http
-            .post(endpoints::XSTS_AUTHORIZE)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.xsts_authorize())
+                    .header("Accept", "application/json")
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
             .await?;
 
         if !response.status().is_success() {
@@ -392,12 +514,13 @@ impl RcAuthClient {
 
         debug!("Logging in to Minecraft Services");
         let response = self
-            .http
-            .post(endpoints::MC_LOGIN)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.mc_login())
+                    .header("Accept", "application/json")
+                    .header("Content-Type", "application/json")
+                    .json(&request)
+            })
             .await?;
 
         if !response.status().is_success() {
@@ -416,15 +539,81 @@ impl RcAuthClient {
         ))
     }
 
+    /// Check which Minecraft products the account owns
+    ///
+    /// Combines the store entitlements with the license listing, which also
+    /// covers Game Pass.
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn check_entitlements(&self, mc_access_token: &str) -> Result<Entitlements> {
+        debug!("Checking entitlements");
+        let store = self
+            .fetch_entitlements(
+                self.config.endpoints.mc_entitlements_store(),
+                mc_access_token,
+                None,
+            )
+            .await?;
+
+        let request_id = generate_request_id()?;
+        let licenses = self
+            .fetch_entitlements(
+                self.config.endpoints.mc_entitlements_license(),
+                mc_access_token,
+                Some(&request_id),
+            )
+            .await?;
+
+        let mut items = licenses.items;
+        for item in store.items {
+            if !items.iter().any(|i| i.name == item.name) {
+                items.push(item);
+            }
+        }
+
+        Ok(Entitlements {
+            items,
+            checked_at: chrono::Utc::now(),
+        })
+    }
+
+    async fn fetch_entitlements(
+        &self,
+        url: String,
+        mc_access_token: &str,
+        request_id: Option<&str>,
+    ) -> Result<EntitlementsResponse> {
+        let response = self
+            .send(|| {
+                let request = self.http.get(&url).bearer_auth(mc_access_token);
+                match request_id {
+                    Some(id) => request.query(&[("requestId", id)]),
+                    None => request,
+                }
+            })
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(RcAuthError::Http {
+                status,
+                body_snippet: body.chars().take(200).collect(),
+            });
+        }
+
+        Ok(response.json().await?)
+    }
+
     /// Fetch Minecraft profile
     #[instrument(skip(self, mc_access_token))]
     pub async fn fetch_profile(&self, mc_access_token: &str) -> Result<McProfile> {
         debug!("Fetching Minecraft profile");
         let response = self
-            .http
-            .get(endpoints::MC_PROFILE)
-            .header("Authorization", format!("Bearer {}", mc_access_token))
-            .send()
+            .send(|| {
+                self.http
+                    .get(self.config.endpoints.mc_profile())
+                    .header("Authorization", format!("Bearer {}", mc_access_token))
+            })
             .await?;
 
         let status = response.status();
@@ -447,13 +636,34 @@ impl RcAuthClient {
     }
 
     /// Complete login flow from authorization code to full session
-    #[instrument(skip(self, code))]
-    pub async fn complete_login_with_code(&self, code: &str) -> Result<Session> {
+    #[instrument(skip(self, code, attempt))]
+    pub async fn complete_login_with_code(
+        &self,
+        code: &str,
+        attempt: &LoginAttempt,
+    ) -> Result<Session> {
         debug!("Starting complete login flow");
 
         // Step 1: Exchange code for MS tokens
-        let ms = self.exchange_code(code).await?;
+        let ms = self.exchange_code(code, attempt).await?;
+
+        self.complete_login_with_ms_tokens(ms).await
+    }
+
+    /// Complete login flow from a device code to full session
+    #[instrument(skip(self, device))]
+    pub async fn complete_login_with_device_code(&self, device: &DeviceCode) -> Result<Session> {
+        debug!("Starting device code login flow");
 
+        // Step 1: Wait for the user to authorize the device code
+        let ms = self.wait_for_device_code(device).await?;
+
+        self.complete_login_with_ms_tokens(ms).await
+    }
+
+    /// Complete login flow from Microsoft tokens to full session
+    #[instrument(skip(self, ms))]
+    pub async fn complete_login_with_ms_tokens(&self, ms: MsTokens) -> Result<Session> {
         // Step 2: Authenticate with Xbox Live
         let xbl = self.xbl_authenticate(&ms.access_token).await?;
 
@@ -463,10 +673,21 @@ impl RcAuthClient {
         // Step 4: Login to Minecraft
         let mc = self.mc_login(&xsts.token, &xsts.uhs).await?;
 
-        // Step 5: Fetch profile
+        // Step 5: Check ownership, so a missing profile can be told apart from
+        // an account that doesn't own the game
+        let entitlements = match self.check_entitlements(&mc.access_token).await {
+            Ok(entitlements) if !entitlements.owns_game() => return Err(RcAuthError::GameNotOwned),
+            Ok(entitlements) => Some(entitlements),
+            Err(e) => {
+                warn!("Failed to check entitlements: {}", e);
+                None
+            }
+        };
+
+        // Step 6: Fetch profile
         let profile = self.fetch_profile(&mc.access_token).await?;
 
-        // Step 6 (optional): Fetch XUID and gamertag
+        // Step 7 (optional): Fetch XUID and gamertag
         let (xuid, gamertag) = match self.fetch_xuid(&xbl.token).await {
             Ok((x, g)) => (Some(x), Some(g)),
             Err(e) => {
@@ -483,37 +704,787 @@ impl RcAuthClient {
             profile,
             xuid,
             gamertag,
+            entitlements,
+            certificates: None,
         })
     }
 
     /// Refresh an existing session
+    ///
+    /// See [`RcAuthClient::refresh_session_in_place`]; on failure the partial
+    /// progress is lost, so prefer that method when the session gets persisted.
     #[instrument(skip(self, session))]
     pub async fn refresh_session(&self, session: &Session) -> Result<Session> {
-        debug!("Refreshing session");
+        let mut session = session.clone();
+        self.refresh_session_in_place(&mut session).await?;
+        Ok(session)
+    }
+
+    /// Get a new Minecraft token for a session, redoing only the expired links
+    ///
+    /// A still-valid XSTS token goes straight to the Minecraft login and a
+    /// still-valid XBL token skips the XBL login. An expired Microsoft token is
+    /// always refreshed, so its refresh token keeps rotating. Each successful step is written into
+    /// `session` right away, so after an error it still holds the tokens that
+    /// were obtained (e.g. a rotated Microsoft refresh token) and should be saved.
+    #[instrument(skip(self, session))]
+    pub async fn refresh_session_in_place(&self, session: &mut Session) -> Result<()> {
+        let needs_xsts = session.xsts.is_expired();
+        let needs_xbl = needs_xsts && session.xbl.is_expired();
+        let needs_ms = session.ms.is_expired();
+        debug!(needs_ms, needs_xbl, needs_xsts, "Refreshing session");
 
         // Step 1: Refresh MS token
-        let refresh_token = session
-            .ms
-            .refresh_token
-            .as_ref()
-            .ok_or(RcAuthError::MissingRefreshToken)?;
+        if needs_ms {
+            let refresh_token = session
+                .ms
+                .refresh_token
+                .clone()
+                .ok_or(RcAuthError::MissingRefreshToken)?;
+            session.ms = self.refresh_ms_token(&refresh_token).await?;
+        }
 
-        let ms = self.refresh_ms_token(refresh_token).await?;
+        // Step 2: Re-authenticate through the rest of the chain
+        if needs_xbl {
+            session.xbl = self.xbl_authenticate(&session.ms.access_token).await?;
+        }
+        if needs_xsts {
+            session.xsts = self.xsts_authorize(&session.xbl.token).await?;
+        }
+        session.mc = self
+            .mc_login(&session.xsts.token, &session.xsts.uhs)
+            .await?;
 
-        // Step 2: Re-authenticate through the chain
-        let xbl = self.xbl_authenticate(&ms.access_token).await?;
-        let xsts = self.xsts_authorize(&xbl.token).await?;
-        let mc = self.mc_login(&xsts.token, &xsts.uhs).await?;
+        // Re-check ownership (e.g. an expired Game Pass), keeping the last result on failure
+        match self.check_entitlements(&session.mc.access_token).await {
+            Ok(entitlements) => session.entitlements = Some(entitlements),
+            Err(e) => warn!("Failed to check entitlements: {}", e),
+        }
 
-        // Keep the same profile and XUID/gamertag
-        Ok(Session {
-            ms,
-            xbl,
-            xsts,
-            mc,
-            profile: session.profile.clone(),
-            xuid: session.xuid.clone(),
-            gamertag: session.gamertag.clone(),
+        // Chat certificates are always kept; the profile only gets re-fetched if enabled
+        if self.config.refresh_profile {
+            match self.fetch_profile(&session.mc.access_token).await {
+                Ok(profile) => session.profile = profile,
+                Err(e) => warn!("Failed to re-fetch profile: {}", e),
+            }
+            match self.fetch_xuid(&session.xbl.token).await {
+                Ok((xuid, gamertag)) => {
+                    session.xuid = Some(xuid);
+                    session.gamertag = Some(gamertag);
+                }
+                Err(e) => warn!("Failed to re-fetch XUID/gamertag: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Turn a failed token endpoint response into a typed OAuth error if possible
+fn oauth_error(status: StatusCode, body: &str) -> RcAuthError {
+    match serde_json::from_str::<MsOAuthErrorResponse>(body) {
+        Ok(error) => OAuthError::from(error).into(),
+        Err(_) => RcAuthError::Http {
+            status,
+            body_snippet: body.chars().take(200).collect(),
+        },
+    }
+}
+
+/// Generate a random UUIDv4 for the license listing `requestId`
+fn generate_request_id() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| RcAuthError::Crypto(format!("Failed to generate request ID: {}", e)))?;
+
+    // Set version 4 and the IETF variant
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{Endpoints, RetryPolicy};
+    use crate::errors::OAuthErrorKind;
+    use serde_json::json;
+    use std::time::Duration;
+    use wiremock::matchers::{
+        body_partial_json, body_string_contains, header, method, path, query_param_contains,
+    };
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    pub(crate) fn mock_client(server: &MockServer) -> RcAuthClient {
+        let base = Url::parse(&server.uri()).unwrap();
+        let mut config = RcAuthConfig::official_desktop();
+        config.endpoints = Endpoints {
+            ms_login: base.clone(),
+            xbl: base.clone(),
+            xsts: base.clone(),
+            mc_services: base,
+        };
+        config.retry = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        RcAuthClient::new(config).unwrap()
+    }
+
+    /// Session for the profile served by `mount_xbox_chain`
+    pub(crate) fn test_session() -> Session {
+        Session {
+            ms: MsTokens::new(
+                "ms-access".to_string(),
+                Some("ms-refresh".to_string()),
+                3600,
+            ),
+            xbl: XblToken {
+                token: "xbl-token".to_string(),
+                uhs: "user-hash".to_string(),
+                not_after: Some(chrono::Utc::now() + chrono::Duration::days(14)),
+            },
+            xsts: XstsToken {
+                token: "xsts-token".to_string(),
+                uhs: "user-hash".to_string(),
+                not_after: Some(chrono::Utc::now() + chrono::Duration::hours(16)),
+            },
+            mc: McToken::new("mc-token".to_string(), 86400),
+            profile: McProfile {
+                id: "069a79f444e94726a5befca90e38aaf5".to_string(),
+                name: "Notch".to_string(),
+                skins: vec![],
+                capes: vec![],
+            },
+            xuid: Some("2535400000000000".to_string()),
+            gamertag: Some("Gamer".to_string()),
+            entitlements: None,
+            certificates: None,
+        }
+    }
+
+    /// `test_session` with every link of the token chain expired
+    pub(crate) fn expired_session() -> Session {
+        let mut session = test_session();
+        session.ms = MsTokens::new("old-access".to_string(), Some("old-refresh".to_string()), 0);
+        session.xbl.not_after = None;
+        session.xsts.not_after = None;
+        session.mc = McToken::new("old-mc".to_string(), 0);
+        session
+    }
+
+    pub(crate) fn ms_token_body(access_token: &str, refresh_token: &str) -> serde_json::Value {
+        json!({
+            "access_token": access_token,
+            "refresh_token": refresh_token,
+            "expires_in": 3600,
+            "token_type": "bearer",
+            "scope": "service::user.auth.xboxlive.com::MBI_SSL"
         })
     }
+
+    /// Mount the XBL -> XSTS -> Minecraft chain that follows the MS token step
+    pub(crate) async fn mount_xbox_chain(server: &MockServer) {
+        Mock::given(method("POST"))
+            .and(path("/user/authenticate"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Token": "xbl-token",
+                "NotAfter": "2099-01-01T00:00:00.0000000Z",
+                "DisplayClaims": { "xui": [{ "uhs": "user-hash" }] }
+            })))
+            .mount(server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/xsts/authorize"))
+            .and(body_partial_json(json!({ "RelyingParty": RP_MINECRAFT })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Token": "xsts-token",
+                "NotAfter": "2099-01-01T00:00:00.0000000Z",
+                "DisplayClaims": { "xui": [{ "uhs": "user-hash" }] }
+            })))
+            .mount(server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/xsts/authorize"))
+            .and(body_partial_json(json!({ "RelyingParty": RP_XBOXLIVE })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Token": "xsts-xbox-token",
+                "DisplayClaims": {
+                    "xui": [{ "uhs": "user-hash", "xid": "2535400000000000", "gtg": "Gamer" }]
+                }
+            })))
+            .mount(server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/authentication/login_with_xbox"))
+            .and(body_partial_json(
+                json!({ "identityToken": "XBL3.0 x=user-hash;xsts-token" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "username": "some-uuid",
+                "access_token": "mc-token",
+                "token_type": "Bearer",
+                "expires_in": 86400
+            })))
+            .mount(server)
+            .await;
+
+        mount_entitlements(server, &["product_minecraft", "game_minecraft"]).await;
+
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile"))
+            .and(header("Authorization", "Bearer mc-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "069a79f444e94726a5befca90e38aaf5",
+                "name": "Notch",
+                "skins": [{
+                    "id": "skin-id",
+                    "state": "ACTIVE",
+                    "url": "http://textures.minecraft.net/texture/abc",
+                    "variant": "CLASSIC"
+                }],
+                "capes": []
+            })))
+            .mount(server)
+            .await;
+    }
+
+    async fn mount_entitlements(server: &MockServer, names: &[&str]) {
+        let items: Vec<_> = names
+            .iter()
+            .map(|name| json!({ "name": name, "signature": "sig" }))
+            .collect();
+
+        Mock::given(method("GET"))
+            .and(path("/entitlements/mcstore"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "items": items,
+                "signature": "sig",
+                "keyId": "1"
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/entitlements/license"))
+            .and(query_param_contains("requestId", "-"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "items": items,
+                "signature": "sig",
+                "keyId": "1"
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_complete_login_with_code() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+        let attempt = client.build_authorize_url(None).unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("grant_type=authorization_code"))
+            .and(body_string_contains("code=auth-code"))
+            .and(body_string_contains(format!(
+                "code_verifier={}",
+                attempt.code_verifier()
+            )))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(ms_token_body("ms-access", "ms-refresh")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        let redirect = format!(
+            "https://login.live.com/oauth20_desktop.srf?code=auth-code&state={}",
+            attempt.state()
+        );
+        let code = client
+            .parse_redirect(&redirect, Some(attempt.state()))
+            .unwrap();
+        let session = client
+            .complete_login_with_code(&code, &attempt)
+            .await
+            .unwrap();
+
+        assert_eq!(session.ms.access_token, "ms-access");
+        assert_eq!(session.ms.refresh_token.as_deref(), Some("ms-refresh"));
+        assert_eq!(session.xbl.token, "xbl-token");
+        assert_eq!(session.xsts.token, "xsts-token");
+        assert_eq!(session.mc.access_token, "mc-token");
+        assert_eq!(session.profile.name, "Notch");
+        assert_eq!(session.account_key(), "069a79f444e94726a5befca90e38aaf5");
+        assert_eq!(session.xuid.as_deref(), Some("2535400000000000"));
+        assert_eq!(session.gamertag.as_deref(), Some("Gamer"));
+        assert_eq!(session.owns_game(), Some(true));
+        assert!(!session.needs_refresh());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_session() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=old-refresh"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(ms_token_body("new-access", "new-refresh")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        let mut session = expired_session();
+        assert!(session.needs_refresh());
+
+        session = client.refresh_session(&session).await.unwrap();
+
+        assert_eq!(session.ms.access_token, "new-access");
+        assert_eq!(session.ms.refresh_token.as_deref(), Some("new-refresh"));
+        assert_eq!(session.mc.access_token, "mc-token");
+        assert_eq!(session.gamertag.as_deref(), Some("Gamer"));
+        assert!(!session.needs_refresh());
+        assert!(!session.xsts.is_expired());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_skips_valid_links() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/user/authenticate"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        // Only the Minecraft token expired: one round trip to log in again
+        let mut session = test_session();
+        session.mc = McToken::new("old-mc".to_string(), 0);
+        client.refresh_session_in_place(&mut session).await.unwrap();
+        assert_eq!(session.mc.access_token, "mc-token");
+        assert_eq!(session.ms.access_token, "ms-access");
+
+        // XSTS expired too, but XBL and MS are still valid
+        session.xsts.not_after = Some(chrono::Utc::now());
+        session.mc = McToken::new("old-mc".to_string(), 0);
+        client.refresh_session_in_place(&mut session).await.unwrap();
+        assert_eq!(session.xsts.token, "xsts-token");
+        assert_eq!(session.xbl.token, "xbl-token");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_renews_expired_ms_token() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("refresh_token=ms-refresh"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(ms_token_body("new-access", "new-refresh")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/user/authenticate"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        // XBL and XSTS are still valid, only the Microsoft token expired
+        let mut session = test_session();
+        session.ms.expires_at = chrono::Utc::now();
+        session.mc = McToken::new("old-mc".to_string(), 0);
+        client.refresh_session_in_place(&mut session).await.unwrap();
+
+        assert_eq!(session.ms.access_token, "new-access");
+        assert_eq!(session.ms.refresh_token.as_deref(), Some("new-refresh"));
+        assert!(!session.ms.is_expired());
+        assert_eq!(session.xbl.token, "xbl-token");
+        assert_eq!(session.mc.access_token, "mc-token");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_progress_on_failure() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(ms_token_body("new-access", "new-refresh")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/user/authenticate"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Token": "new-xbl-token",
+                "NotAfter": "2099-01-01T00:00:00.0000000Z",
+                "DisplayClaims": { "xui": [{ "uhs": "user-hash" }] }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/xsts/authorize"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let mut session = expired_session();
+        let result = client.refresh_session_in_place(&mut session).await;
+
+        assert!(matches!(result, Err(RcAuthError::Http { .. })));
+        assert_eq!(session.ms.refresh_token.as_deref(), Some("new-refresh"));
+        assert_eq!(session.xbl.token, "new-xbl-token");
+        assert!(!session.xbl.is_expired());
+        assert!(session.needs_refresh());
+    }
+
+    #[test]
+    fn test_not_after_parsing() {
+        let xbl: XblToken = serde_json::from_value(json!({
+            "token": "t",
+            "uhs": "u",
+            "not_after": "2099-01-01T00:00:00.0000000Z"
+        }))
+        .unwrap();
+        assert!(!xbl.is_expired());
+
+        // Garbage from older versions is treated as unknown, i.e. expired
+        let xbl: XblToken = serde_json::from_value(json!({
+            "token": "t",
+            "uhs": "u",
+            "not_after": "not a date"
+        }))
+        .unwrap();
+        assert_eq!(xbl.not_after, None);
+        assert!(xbl.is_expired());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_grant_is_not_retried() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "invalid_grant",
+                "error_description": "The refresh token has expired."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = client.refresh_ms_token("expired").await;
+        assert!(matches!(
+            &result,
+            Err(RcAuthError::OAuth(OAuthError {
+                kind: OAuthErrorKind::InvalidGrant,
+                ..
+            }))
+        ));
+        assert!(result.unwrap_err().requires_relogin());
+    }
+
+    #[tokio::test]
+    async fn test_oauth_error_details() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("refresh_token=needs-mfa"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "interaction_required",
+                "error_description": "AADSTS50076: Due to a configuration change you must use MFA.",
+                "error_codes": [50076],
+                "correlation_id": "abc-123"
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("refresh_token=bad-client"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": "invalid_client",
+                "error_description": "The client does not exist."
+            })))
+            .mount(&server)
+            .await;
+
+        let Err(RcAuthError::OAuth(error)) = client.refresh_ms_token("needs-mfa").await else {
+            panic!("expected an OAuth error");
+        };
+        assert_eq!(error.kind, OAuthErrorKind::InteractionRequired);
+        assert_eq!(error.error_codes, vec![50076]);
+        assert_eq!(error.correlation_id.as_deref(), Some("abc-123"));
+        assert!(error.requires_relogin());
+
+        let Err(RcAuthError::OAuth(error)) = client.refresh_ms_token("bad-client").await else {
+            panic!("expected an OAuth error");
+        };
+        assert_eq!(error.kind, OAuthErrorKind::InvalidClient);
+        assert!(!error.requires_relogin());
+    }
+
+    #[tokio::test]
+    async fn test_xsts_denied() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/xsts/authorize"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "Identity": "0",
+                "XErr": 2148916233u64,
+                "Message": "",
+                "Redirect": "https://start.ui.xboxlive.com/CreateAccount"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = client.xsts_authorize("xbl-token").await;
+        assert!(matches!(
+            result,
+            Err(RcAuthError::XstsDenied(XstsError::NoXboxAccount))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_xbl_retries_with_d_prefix() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/user/authenticate"))
+            .and(body_partial_json(
+                json!({ "Properties": { "RpsTicket": "d=ms-access" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "Token": "xbl-token",
+                "DisplayClaims": { "xui": [{ "uhs": "user-hash" }] }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/user/authenticate"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let xbl = client.xbl_authenticate("ms-access").await.unwrap();
+        assert_eq!(xbl.token, "xbl-token");
+        assert_eq!(xbl.uhs, "user-hash");
+    }
+
+    #[tokio::test]
+    async fn test_profile_not_found() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "path": "/minecraft/profile",
+                "error": "NOT_FOUND"
+            })))
+            .mount(&server)
+            .await;
+
+        let result = client.fetch_profile("mc-token").await;
+        assert!(matches!(result, Err(RcAuthError::MinecraftProfileNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_login_without_game() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        mount_entitlements(&server, &[]).await;
+        mount_xbox_chain(&server).await;
+
+        let ms = MsTokens::new(
+            "ms-access".to_string(),
+            Some("ms-refresh".to_string()),
+            3600,
+        );
+        let result = client.complete_login_with_ms_tokens(ms).await;
+        assert!(matches!(result, Err(RcAuthError::GameNotOwned)));
+    }
+
+    #[tokio::test]
+    async fn test_login_owns_game_without_profile() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        let ms = MsTokens::new(
+            "ms-access".to_string(),
+            Some("ms-refresh".to_string()),
+            3600,
+        );
+        let result = client.complete_login_with_ms_tokens(ms).await;
+        assert!(matches!(result, Err(RcAuthError::MinecraftProfileNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_game_pass_entitlements() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("GET"))
+            .and(path("/entitlements/mcstore"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "items": [] })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/entitlements/license"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "items": [{ "name": "game_minecraft", "source": "GAMEPASS" }]
+            })))
+            .mount(&server)
+            .await;
+
+        let entitlements = client.check_entitlements("mc-token").await.unwrap();
+        assert!(entitlements.owns_game());
+        assert_eq!(entitlements.items[0].source.as_deref(), Some("GAMEPASS"));
+    }
+
+    #[tokio::test]
+    async fn test_device_code_login() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_connect.srf"))
+            .and(body_string_contains("response_type=device_code"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_code": "ABCD-EFGH",
+                "device_code": "device-123",
+                "verification_uri": "https://www.microsoft.com/link",
+                "expires_in": 900,
+                "interval": 0
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("device_code=device-123"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "authorization_pending"
+            })))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("device_code=device-123"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(ms_token_body("ms-access", "ms-refresh")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        let device = client.request_device_code().await.unwrap();
+        assert_eq!(device.user_code, "ABCD-EFGH");
+        assert_eq!(device.verification_uri, "https://www.microsoft.com/link");
+
+        let session = client
+            .complete_login_with_device_code(&device)
+            .await
+            .unwrap();
+        assert_eq!(session.profile.name, "Notch");
+        assert_eq!(session.ms.refresh_token.as_deref(), Some("ms-refresh"));
+    }
+
+    #[tokio::test]
+    async fn test_device_code_declined() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "authorization_declined"
+            })))
+            .mount(&server)
+            .await;
+
+        let device = DeviceCode::from(DeviceCodeResponse {
+            user_code: "ABCD".to_string(),
+            device_code: "device-123".to_string(),
+            verification_uri: "https://www.microsoft.com/link".to_string(),
+            expires_in: 900,
+            interval: 0,
+            message: None,
+        });
+
+        let result = client.wait_for_device_code(&device).await;
+        assert!(matches!(result, Err(RcAuthError::AuthorizationDeclined)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_device_code_is_not_polled() {
+        let client = RcAuthClient::new(RcAuthConfig::default()).unwrap();
+        let device = DeviceCode::from(DeviceCodeResponse {
+            user_code: "ABCD".to_string(),
+            device_code: "device-123".to_string(),
+            verification_uri: "https://www.microsoft.com/link".to_string(),
+            expires_in: 0,
+            interval: 5,
+            message: None,
+        });
+
+        let result = client.complete_login_with_device_code(&device).await;
+        assert!(matches!(result, Err(RcAuthError::DeviceCodeExpired)));
+    }
 }
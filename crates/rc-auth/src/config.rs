@@ -1,14 +1,120 @@
 use std::time::Duration;
 use url::Url;
 
-/// Microsoft authentication endpoints
+/// Default base URLs of the services used during authentication
 pub mod endpoints {
-    pub const MS_AUTHORIZE: &str = "https://login.live.com/oauth20_authorize.srf";
-    pub const MS_TOKEN: &str = "https://login.live.com/oauth20_token.srf";
-    pub const XBL_AUTHENTICATE: &str = "https://user.auth.xboxlive.com/user/authenticate";
-    pub const XSTS_AUTHORIZE: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
-    pub const MC_LOGIN: &str = "https://api.minecraftservices.com/authentication/login_with_xbox";
-    pub const MC_PROFILE: &str = "https://api.minecraftservices.com/minecraft/profile";
+    pub const MS_LOGIN: &str = "https://login.live.com";
+    pub const XBL_USER_AUTH: &str = "https://user.auth.xboxlive.com";
+    pub const XSTS_AUTH: &str = "https://xsts.auth.xboxlive.com";
+    pub const MC_SERVICES: &str = "https://api.minecraftservices.com";
+}
+
+/// Base URLs of the services used during authentication
+///
+/// Defaults to the production services. Point these at a local server to test
+/// the client end to end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    /// Microsoft OAuth (authorize, token and device code endpoints)
+    pub ms_login: Url,
+    /// Xbox Live user authentication
+    pub xbl: Url,
+    /// XSTS authorization
+    pub xsts: Url,
+    /// Minecraft services (login and profile)
+    pub mc_services: Url,
+}
+
+impl Endpoints {
+    pub fn ms_authorize(&self) -> String {
+        join(&self.ms_login, "oauth20_authorize.srf")
+    }
+
+    pub fn ms_token(&self) -> String {
+        join(&self.ms_login, "oauth20_token.srf")
+    }
+
+    pub fn ms_device_code(&self) -> String {
+        join(&self.ms_login, "oauth20_connect.srf")
+    }
+
+    pub fn xbl_authenticate(&self) -> String {
+        join(&self.xbl, "user/authenticate")
+    }
+
+    pub fn xsts_authorize(&self) -> String {
+        join(&self.xsts, "xsts/authorize")
+    }
+
+    pub fn mc_login(&self) -> String {
+        join(&self.mc_services, "authentication/login_with_xbox")
+    }
+
+    pub fn mc_entitlements_store(&self) -> String {
+        join(&self.mc_services, "entitlements/mcstore")
+    }
+
+    pub fn mc_entitlements_license(&self) -> String {
+        join(&self.mc_services, "entitlements/license")
+    }
+
+    pub fn player_attributes(&self) -> String {
+        join(&self.mc_services, "player/attributes")
+    }
+
+    pub fn player_certificates(&self) -> String {
+        join(&self.mc_services, "player/certificates")
+    }
+
+    pub fn mc_profile(&self) -> String {
+        join(&self.mc_services, "minecraft/profile")
+    }
+
+    pub fn mc_profile_skins(&self) -> String {
+        join(&self.mc_services, "minecraft/profile/skins")
+    }
+
+    pub fn mc_profile_active_skin(&self) -> String {
+        join(&self.mc_services, "minecraft/profile/skins/active")
+    }
+
+    pub fn mc_profile_active_cape(&self) -> String {
+        join(&self.mc_services, "minecraft/profile/capes/active")
+    }
+
+    pub fn mc_profile_namechange(&self) -> String {
+        join(&self.mc_services, "minecraft/profile/namechange")
+    }
+
+    pub fn mc_profile_name(&self, name: &str) -> String {
+        join(
+            &self.mc_services,
+            &format!("minecraft/profile/name/{}", name),
+        )
+    }
+
+    pub fn mc_profile_name_available(&self, name: &str) -> String {
+        join(
+            &self.mc_services,
+            &format!("minecraft/profile/name/{}/available", name),
+        )
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            ms_login: Url::parse(endpoints::MS_LOGIN).expect("valid MS login URL"),
+            xbl: Url::parse(endpoints::XBL_USER_AUTH).expect("valid XBL URL"),
+            xsts: Url::parse(endpoints::XSTS_AUTH).expect("valid XSTS URL"),
+            mc_services: Url::parse(endpoints::MC_SERVICES).expect("valid Minecraft services URL"),
+        }
+    }
+}
+
+/// Append a path to a base URL, tolerating a trailing slash on the base
+fn join(base: &Url, path: &str) -> String {
+    format!("{}/{}", base.as_str().trim_end_matches('/'), path)
 }
 
 /// Official Minecraft launcher OAuth configuration
@@ -30,6 +136,12 @@ pub mod official {
 /// Standard OAuth scope for custom apps
 pub const STANDARD_SCOPE: &str = "XboxLive.signin offline_access";
 
+/// Grant type used when polling the token endpoint during the device code flow
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Extra delay added to the polling interval when the server answers `slow_down`
+pub const DEVICE_CODE_SLOW_DOWN_STEP: Duration = Duration::from_secs(5);
+
 /// Relying parties
 pub const RP_MINECRAFT: &str = "rp://api.minecraftservices.com/";
 pub const RP_XBOXLIVE: &str = "http://xboxlive.com";
@@ -38,10 +150,11 @@ pub const RP_XBOXLIVE: &str = "http://xboxlive.com";
 pub const TOKEN_EXPIRY_SKEW: Duration = Duration::from_secs(300);
 
 /// Authentication flow flavor
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum AuthorizeFlavor {
     /// Official Minecraft launcher flow (recommended for development)
     /// Uses official client ID and doesn't require app approval
+    #[default]
     OfficialDesktop,
 
     /// Standard OAuth2 code flow for custom approved apps
@@ -49,12 +162,6 @@ pub enum AuthorizeFlavor {
     StandardCode,
 }
 
-impl Default for AuthorizeFlavor {
-    fn default() -> Self {
-        Self::OfficialDesktop
-    }
-}
-
 /// HTTP client configuration
 #[derive(Debug, Clone)]
 pub struct HttpTimeouts {
@@ -72,10 +179,15 @@ impl Default for HttpTimeouts {
 }
 
 /// Retry policy configuration
+///
+/// Applied to connect errors, timeouts, 5xx and 429 responses.
 #[derive(Debug, Clone)]
 pub struct RetryPolicy {
     pub max_retries: u32,
+    /// Delay before the first retry, doubled on every attempt
     pub base_delay: Duration,
+    /// Upper bound for a single delay, including `Retry-After`
+    pub max_delay: Duration,
 }
 
 impl Default for RetryPolicy {
@@ -83,6 +195,7 @@ impl Default for RetryPolicy {
         Self {
             max_retries: 3,
             base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
         }
     }
 }
@@ -107,6 +220,14 @@ pub struct RcAuthConfig {
 
     /// Retry policy
     pub retry: RetryPolicy,
+
+    /// Service base URLs
+    pub endpoints: Endpoints,
+
+    /// Re-fetch the profile, XUID and gamertag when refreshing a session
+    ///
+    /// Costs two extra round trips per refresh but picks up name, skin and cape changes.
+    pub refresh_profile: bool,
 }
 
 impl RcAuthConfig {
@@ -119,6 +240,8 @@ impl RcAuthConfig {
             http_timeouts: HttpTimeouts::default(),
             user_agent: Some("rauncher-mc".to_string()),
             retry: RetryPolicy::default(),
+            endpoints: Endpoints::default(),
+            refresh_profile: false,
         }
     }
 
@@ -131,6 +254,8 @@ impl RcAuthConfig {
             http_timeouts: HttpTimeouts::default(),
             user_agent: Some("rauncher-mc".to_string()),
             retry: RetryPolicy::default(),
+            endpoints: Endpoints::default(),
+            refresh_profile: false,
         }
     }
 }
@@ -54,6 +54,9 @@ pub struct EncryptedBlob {
     pub aad_version: String,
 }
 
+/// Version tag bound into the AAD of every blob
+pub const AAD_VERSION: &str = "v1";
+
 /// Encrypt plaintext using AES-256-GCM
 pub fn encrypt(key: &EncryptionKey, plaintext: &[u8], account_key: &str) -> Result<EncryptedBlob> {
     let cipher = Aes256Gcm::new(key.as_bytes().into());
@@ -64,7 +67,7 @@ pub fn encrypt(key: &EncryptionKey, plaintext: &[u8], account_key: &str) -> Resu
     let nonce = &nonce_bytes.into();
 
     // AAD format: "rc-auth|v1|{account_key}"
-    let aad_version = "v1".to_string();
+    let aad_version = AAD_VERSION.to_string();
     let aad = format!("rc-auth|{}|{}", aad_version, account_key);
 
     // Encrypt with AAD
@@ -87,6 +90,13 @@ pub fn encrypt(key: &EncryptionKey, plaintext: &[u8], account_key: &str) -> Resu
 
 /// Decrypt ciphertext using AES-256-GCM
 pub fn decrypt(key: &EncryptionKey, blob: &EncryptedBlob, account_key: &str) -> Result<Vec<u8>> {
+    if blob.aad_version != AAD_VERSION {
+        return Err(RcAuthError::Crypto(format!(
+            "Unsupported blob version {}",
+            blob.aad_version
+        )));
+    }
+
     let cipher = Aes256Gcm::new(key.as_bytes().into());
 
     // Decode nonce
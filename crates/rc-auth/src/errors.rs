@@ -15,8 +15,20 @@ pub enum RcAuthError {
         body_snippet: String,
     },
 
-    #[error("OAuth invalid_grant - refresh token may be expired")]
-    OAuthInvalidGrant,
+    #[error("OAuth error: {0}")]
+    OAuth(#[from] OAuthError),
+
+    #[error("Authorization pending - the user hasn't entered the device code yet")]
+    AuthorizationPending,
+
+    #[error("Polling too fast - the device code interval must be increased")]
+    SlowDown,
+
+    #[error("Device code expired before the user completed authorization")]
+    DeviceCodeExpired,
+
+    #[error("User declined the authorization request")]
+    AuthorizationDeclined,
 
     #[error("Xbox Live authentication failed after retry")]
     XblBadRequest,
@@ -24,21 +36,45 @@ pub enum RcAuthError {
     #[error("XSTS authorization denied: {0}")]
     XstsDenied(#[from] XstsError),
 
+    #[error("Account doesn't own Minecraft: Java Edition")]
+    GameNotOwned,
+
     #[error("Minecraft profile not found - user may not own Minecraft or hasn't created a profile")]
     MinecraftProfileNotFound,
 
+    #[error("Yggdrasil server error {error}: {message}")]
+    Yggdrasil { error: String, message: String },
+
     #[error("Invalid redirect URI or missing code")]
     InvalidRedirect,
 
     #[error("OAuth state mismatch - possible CSRF attack")]
     StateMismatch,
 
+    #[error("Timed out waiting for the OAuth redirect")]
+    RedirectTimeout,
+
     #[error("JSON serialization/deserialization error: {0}")]
     Serde(#[from] serde_json::Error),
 
     #[error("URL parse error: {0}")]
     UrlParse(#[from] url::ParseError),
 
+    #[error("Name change failed: {0}")]
+    NameChange(#[from] NameChangeError),
+
+    #[error("Cape {0} is not owned by this profile")]
+    CapeNotOwned(String),
+
+    #[error("Invalid skin image: {0}")]
+    InvalidSkin(String),
+
+    #[error("Invalid player name: {0:?}")]
+    InvalidPlayerName(String),
+
+    #[error("No Microsoft account stored under {0}")]
+    AccountNotFound(String),
+
     #[error("Missing refresh token - cannot refresh session")]
     MissingRefreshToken,
 
@@ -54,9 +90,20 @@ pub enum RcAuthError {
     #[error("Keyring error: {0}")]
     Keyring(String),
 
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("Token storage is not in {0} mode")]
+    WrongKeyMode(crate::key_manager::KeyMode),
+
     #[error("Corrupted storage - decryption or integrity check failed")]
     CorruptedStore,
 
+    #[error(
+        "Storage format version {found} is newer than supported version {supported} - update the launcher"
+    )]
+    UnsupportedStoreVersion { found: u32, supported: u32 },
+
     #[error("Lock timeout - another process may be using the storage")]
     LockTimeout,
 
@@ -64,6 +111,117 @@ pub enum RcAuthError {
     Base64(#[from] base64::DecodeError),
 }
 
+/// Microsoft OAuth error returned by the token endpoint
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind}{}", description.as_ref().map(|d| format!(" - {}", d)).unwrap_or_default())]
+pub struct OAuthError {
+    pub kind: OAuthErrorKind,
+    /// Human readable `error_description`
+    pub description: Option<String>,
+    /// AADSTS error codes
+    pub error_codes: Vec<u64>,
+    pub correlation_id: Option<String>,
+}
+
+impl OAuthError {
+    /// Whether the user has to sign in again to recover
+    pub fn requires_relogin(&self) -> bool {
+        self.kind.requires_relogin()
+    }
+}
+
+/// OAuth error codes from the `error` field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OAuthErrorKind {
+    /// Refresh token or authorization code is expired, revoked or already used
+    InvalidGrant,
+    /// The user must interact with the sign-in page (e.g. MFA or password change)
+    InteractionRequired,
+    /// The user must consent to the requested scopes again
+    ConsentRequired,
+    LoginRequired,
+    AccessDenied,
+    /// Client ID is wrong or not allowed - a configuration problem
+    InvalidClient,
+    UnauthorizedClient,
+    InvalidRequest,
+    InvalidScope,
+    UnsupportedGrantType,
+    TemporarilyUnavailable,
+    ServerError,
+    Unknown(String),
+}
+
+impl OAuthErrorKind {
+    /// Parse the `error` field of an OAuth error response
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "invalid_grant" => Self::InvalidGrant,
+            "interaction_required" => Self::InteractionRequired,
+            "consent_required" => Self::ConsentRequired,
+            "login_required" => Self::LoginRequired,
+            "access_denied" => Self::AccessDenied,
+            "invalid_client" => Self::InvalidClient,
+            "unauthorized_client" => Self::UnauthorizedClient,
+            "invalid_request" => Self::InvalidRequest,
+            "invalid_scope" => Self::InvalidScope,
+            "unsupported_grant_type" => Self::UnsupportedGrantType,
+            "temporarily_unavailable" => Self::TemporarilyUnavailable,
+            "server_error" => Self::ServerError,
+            code => Self::Unknown(code.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::InvalidGrant => "invalid_grant",
+            Self::InteractionRequired => "interaction_required",
+            Self::ConsentRequired => "consent_required",
+            Self::LoginRequired => "login_required",
+            Self::AccessDenied => "access_denied",
+            Self::InvalidClient => "invalid_client",
+            Self::UnauthorizedClient => "unauthorized_client",
+            Self::InvalidRequest => "invalid_request",
+            Self::InvalidScope => "invalid_scope",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::TemporarilyUnavailable => "temporarily_unavailable",
+            Self::ServerError => "server_error",
+            Self::Unknown(code) => code,
+        }
+    }
+
+    /// Whether the user has to sign in again to recover
+    pub fn requires_relogin(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidGrant
+                | Self::InteractionRequired
+                | Self::ConsentRequired
+                | Self::LoginRequired
+                | Self::AccessDenied
+        )
+    }
+}
+
+impl std::fmt::Display for OAuthErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl RcAuthError {
+    /// Whether the user has to sign in again to recover from this error
+    pub fn requires_relogin(&self) -> bool {
+        match self {
+            Self::OAuth(e) => e.requires_relogin(),
+            Self::MissingRefreshToken | Self::AuthorizationDeclined | Self::DeviceCodeExpired => {
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 /// XSTS-specific error codes from XErr field
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum XstsError {
@@ -96,4 +254,19 @@ impl XstsError {
     }
 }
 
+/// Reasons a profile name can't be changed
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NameChangeError {
+    #[error("Name is already taken")]
+    Duplicate,
+
+    #[error("Name is not allowed")]
+    NotAllowed,
+
+    #[error("Name was changed too recently")]
+    Cooldown {
+        changed_at: Option<chrono::DateTime<chrono::Utc>>,
+    },
+}
+
 pub type Result<T> = std::result::Result<T, RcAuthError>;
@@ -1,28 +1,41 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use fs2::FileExt;
+use serde::{Deserialize, Serialize};
 use tokio::fs;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, broadcast};
 
-use crate::crypto::{self, EncryptedBlob};
+use crate::account::Account;
+use crate::bundle::AccountBundle;
+use crate::crypto::{self, EncryptedBlob, EncryptionKey};
 use crate::errors::{RcAuthError, Result};
-use crate::key_manager::KeyManager;
+#[cfg(test)]
+use crate::key_manager::Fault;
+use crate::key_manager::{
+    KdfParams, KeyManager, KeyMeta, KeyMode, PendingKey, STORE_FORMAT_VERSION,
+};
 use crate::secret::SecretProvider;
 use crate::session::Session;
-use crate::store::TokenStore;
+use crate::store::{AccountUpdate, STORE_EVENT_CAPACITY, StoreEvent, TokenStore};
 
 /// File-based encrypted token store
 ///
-/// Stores encrypted authentication sessions in per-account files.
+/// Stores encrypted accounts (Microsoft sessions and offline players) in per-account files.
 /// Uses OS keyring for key management with passphrase fallback.
 ///
 /// # Directory Structure
 /// ```text
 /// ~/.config/rauncher/rc-auth/
-/// ├── meta.json              # Storage metadata
+/// ├── meta.json              # Storage metadata and format version
 /// ├── lock                   # Advisory lock file
+/// ├── backups/
+/// │   └── v1-<timestamp>/    # Copy taken before migrating from format 1
+/// ├── rotation/              # Only while a key rotation is in progress
+/// │   ├── journal.json       # Accounts being rotated and the new key's metadata
+/// │   └── uuid1.json         # Account 1 encrypted under the new key
 /// └── accounts/
 ///     ├── uuid1.json         # Encrypted session for account 1
 ///     └── uuid2.json         # Encrypted session for account 2
@@ -32,9 +45,114 @@ pub struct FileTokenStore {
     storage_dir: PathBuf,
     accounts_dir: PathBuf,
     lock_file: PathBuf,
+    rotation_dir: PathBuf,
     key_manager: Arc<RwLock<KeyManager>>,
-    /// In-memory cache for recently accessed sessions
-    cache: Arc<RwLock<HashMap<String, Session>>>,
+    /// In-memory cache for recently accessed accounts, stamped with the file they came from
+    cache: Arc<RwLock<HashMap<String, CachedAccount>>>,
+    events: broadcast::Sender<StoreEvent>,
+    options: FileStoreOptions,
+    /// Step of `rotate_key` at which to simulate a failure
+    #[cfg(test)]
+    fail_at: std::sync::Mutex<Option<(RotationStep, Fault)>>,
+}
+
+/// Interval between attempts while waiting for the storage lock
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Tuning options for [`FileTokenStore`]
+#[derive(Debug, Clone)]
+pub struct FileStoreOptions {
+    /// How long to wait for another process to release the storage lock
+    /// before failing with `RcAuthError::LockTimeout`
+    pub lock_timeout: Duration,
+    /// Argon2id parameters for passphrase-derived keys
+    ///
+    /// When set, a store derived with other parameters is re-encrypted under
+    /// these the next time it's unlocked with the passphrase. When unset, each
+    /// store keeps the parameters it was created with, and new stores use
+    /// `KdfParams::default()`.
+    pub kdf_params: Option<KdfParams>,
+}
+
+impl Default for FileStoreOptions {
+    fn default() -> Self {
+        Self {
+            lock_timeout: Duration::from_secs(10),
+            kdf_params: None,
+        }
+    }
+}
+
+/// Journal of an in-progress key rotation
+#[derive(Debug, Serialize, Deserialize)]
+struct RotationJournal {
+    started_at: chrono::DateTime<chrono::Utc>,
+    account_keys: Vec<String>,
+    /// Metadata of the new key; the key itself is never written here
+    meta: KeyMeta,
+}
+
+/// Steps of `rotate_key` after which tests can simulate a failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RotationStep {
+    /// One account is staged under the new key
+    PartiallyStaged,
+    /// Every account is staged under the new key
+    Staged,
+    /// The new key is in the keyring but not yet in `meta.json`, checked by
+    /// `KeyManager::commit_rotation`
+    #[cfg(test)]
+    KeySaved,
+    /// The new key is in the keyring and `meta.json`
+    Committed,
+    /// One staged file has been swapped in
+    Swapping,
+}
+
+/// Outcome of [`FileTokenStore::import_accounts`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Keys of the accounts written to the store
+    pub imported: Vec<String>,
+    /// Keys of the accounts whose local copy was newer and kept
+    pub skipped: Vec<String>,
+}
+
+/// Cached account and the stamp of the file it was read from
+#[derive(Debug, Clone)]
+struct CachedAccount {
+    account: Account,
+    stamp: FileStamp,
+}
+
+/// Identity of an account file's current contents
+///
+/// Files are replaced through an atomic rename, so on Unix the inode changes
+/// on every write even if the mtime and size don't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileStamp {
+    modified: std::time::SystemTime,
+    len: u64,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+impl FileStamp {
+    /// Stamp the file at `path`, or `None` if it doesn't exist
+    async fn read(path: &Path) -> Result<Option<Self>> {
+        let metadata = match fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(Self {
+            modified: metadata.modified()?,
+            len: metadata.len(),
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(&metadata),
+        }))
+    }
 }
 
 impl FileTokenStore {
@@ -46,10 +164,20 @@ impl FileTokenStore {
     pub async fn new(
         storage_dir: impl AsRef<Path>,
         secret_provider: Arc<dyn SecretProvider>,
+    ) -> Result<Self> {
+        Self::with_options(storage_dir, secret_provider, FileStoreOptions::default()).await
+    }
+
+    /// Create a new file-based token store with custom options
+    pub async fn with_options(
+        storage_dir: impl AsRef<Path>,
+        secret_provider: Arc<dyn SecretProvider>,
+        options: FileStoreOptions,
     ) -> Result<Self> {
         let storage_dir = storage_dir.as_ref().to_path_buf();
         let accounts_dir = storage_dir.join("accounts");
         let lock_file = storage_dir.join("lock");
+        let rotation_dir = storage_dir.join("rotation");
 
         // Create directories
         fs::create_dir_all(&storage_dir).await?;
@@ -65,15 +193,122 @@ impl FileTokenStore {
         }
 
         // Initialize key manager
-        let key_manager = KeyManager::new(&storage_dir, secret_provider).await?;
+        let key_manager =
+            KeyManager::with_params(&storage_dir, secret_provider, options.kdf_params).await?;
 
-        Ok(Self {
+        let store = Self {
             storage_dir,
             accounts_dir,
             lock_file,
+            rotation_dir,
             key_manager: Arc::new(RwLock::new(key_manager)),
             cache: Arc::new(RwLock::new(HashMap::new())),
-        })
+            events: broadcast::channel(STORE_EVENT_CAPACITY).0,
+            options,
+            #[cfg(test)]
+            fail_at: std::sync::Mutex::new(None),
+        };
+        store.resume_rotation().await?;
+        store.migrate().await?;
+
+        // Re-derive under the configured parameters now that the passphrase is known.
+        // The store stays usable under its current key if this fails, and the
+        // upgrade is tried again on the next unlock.
+        let kdf_upgrade = store.key_manager.write().await.take_kdf_upgrade();
+        if let Some(pending) = kdf_upgrade {
+            tracing::info!("Upgrading key derivation parameters of token storage");
+            if let Err(e) = store.rotate_to(pending).await {
+                tracing::warn!("Failed to upgrade key derivation parameters: {}", e);
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Upgrade an older on-disk format to `STORE_FORMAT_VERSION`
+    ///
+    /// The store is backed up to `backups/v<old>-<timestamp>/` first. Each step
+    /// bumps the version in `meta.json` once done, so an interrupted migration
+    /// resumes from the last completed step.
+    async fn migrate(&self) -> Result<()> {
+        if self.key_manager.read().await.version() >= STORE_FORMAT_VERSION {
+            return Ok(());
+        }
+
+        let _lock = self.acquire_lock().await?;
+
+        // Another process may have migrated while we waited for the lock
+        let mut version = self.key_manager.read().await.version();
+        if version >= STORE_FORMAT_VERSION {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "Migrating token storage from format {} to {}",
+            version,
+            STORE_FORMAT_VERSION
+        );
+        self.backup(version).await?;
+
+        while version < STORE_FORMAT_VERSION {
+            match version {
+                1 => self.migrate_v1_to_v2().await?,
+                _ => {
+                    return Err(RcAuthError::InvalidResponse(format!(
+                        "No migration from storage format {}",
+                        version
+                    )));
+                }
+            }
+
+            version += 1;
+            self.key_manager
+                .write()
+                .await
+                .set_version(&self.storage_dir, version)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy `meta.json` and every account file into a fresh backup directory
+    async fn backup(&self, version: u32) -> Result<PathBuf> {
+        let backup_dir = self.storage_dir.join("backups").join(format!(
+            "v{}-{}",
+            version,
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+        ));
+        let backup_accounts = backup_dir.join("accounts");
+        fs::create_dir_all(&backup_accounts).await?;
+
+        fs::copy(
+            self.storage_dir.join("meta.json"),
+            backup_dir.join("meta.json"),
+        )
+        .await?;
+        for key in self.list_account_keys().await {
+            let file_name = format!("{}.json", key);
+            fs::copy(self.account_path(&key), backup_accounts.join(file_name)).await?;
+        }
+
+        tracing::info!("Backed up token storage to {}", backup_dir.display());
+        Ok(backup_dir)
+    }
+
+    /// Format 2: rewrite bare `Session` payloads as tagged `Account`s
+    ///
+    /// Files that can't be read are left as they are rather than blocking the
+    /// migration of every other account.
+    async fn migrate_v1_to_v2(&self) -> Result<()> {
+        for key in self.list_account_keys().await {
+            match self.load_from_disk(&key).await {
+                Ok(Some(account)) => self.save_to_disk(&key, &account).await?,
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Skipping unreadable session {} in migration: {}", key, e),
+            }
+        }
+        Ok(())
     }
 
     /// Get default storage directory for the current platform
@@ -91,23 +326,57 @@ impl FileTokenStore {
         self.accounts_dir.join(format!("{}.json", account_key))
     }
 
-    /// Acquire an exclusive lock on the storage
+    /// Acquire an exclusive lock on the storage, for writes
     async fn acquire_lock(&self) -> Result<std::fs::File> {
-        let lock_file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(false)
-            .open(&self.lock_file)?;
+        self.lock(true).await
+    }
 
-        lock_file
-            .try_lock_exclusive()
-            .map_err(|_| RcAuthError::LockTimeout)?;
+    /// Acquire a shared lock on the storage, for reads
+    async fn acquire_shared_lock(&self) -> Result<std::fs::File> {
+        self.lock(false).await
+    }
 
-        Ok(lock_file)
+    /// Wait on a blocking thread until the lock is granted or `lock_timeout` elapses
+    ///
+    /// The lock is released when the returned file is dropped. Locks are held per
+    /// open file, so a task must not take the lock again while already holding it.
+    async fn lock(&self, exclusive: bool) -> Result<std::fs::File> {
+        let path = self.lock_file.clone();
+        let timeout = self.options.lock_timeout;
+
+        tokio::task::spawn_blocking(move || {
+            let lock_file = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .open(&path)?;
+
+            let started = Instant::now();
+            loop {
+                let result = if exclusive {
+                    FileExt::try_lock_exclusive(&lock_file)
+                } else {
+                    FileExt::try_lock_shared(&lock_file)
+                };
+
+                match result {
+                    Ok(()) => return Ok(lock_file),
+                    Err(e) if e.kind() != fs2::lock_contended_error().kind() => {
+                        return Err(e.into());
+                    }
+                    Err(_) if started.elapsed() >= timeout => {
+                        return Err(RcAuthError::LockTimeout);
+                    }
+                    Err(_) => std::thread::sleep(LOCK_POLL_INTERVAL),
+                }
+            }
+        })
+        .await
+        .map_err(|e| RcAuthError::StorageIo(std::io::Error::other(e)))?
     }
 
-    /// Load and decrypt a session from disk
-    async fn load_from_disk(&self, account_key: &str) -> Result<Option<Session>> {
+    /// Load and decrypt an account from disk
+    async fn load_from_disk(&self, account_key: &str) -> Result<Option<Account>> {
         let path = self.account_path(account_key);
 
         if !path.exists() {
@@ -123,104 +392,482 @@ impl FileTokenStore {
         let key_manager = self.key_manager.read().await;
         let plaintext = crypto::decrypt(key_manager.key(), &encrypted, account_key)?;
 
-        // Deserialize session
-        let session: Session = serde_json::from_slice(&plaintext)
-            .map_err(|e| RcAuthError::InvalidResponse(format!("Invalid session data: {}", e)))?;
+        // Deserialize account, falling back to a bare session written before
+        // offline accounts existed
+        let account = match serde_json::from_slice::<Account>(&plaintext) {
+            Ok(account) => account,
+            Err(_) => serde_json::from_slice::<Session>(&plaintext)
+                .map(Account::from)
+                .map_err(|e| {
+                    RcAuthError::InvalidResponse(format!("Invalid session data: {}", e))
+                })?,
+        };
 
-        Ok(Some(session))
+        Ok(Some(account))
     }
 
-    /// Encrypt and save a session to disk
-    async fn save_to_disk(&self, account_key: &str, session: &Session) -> Result<()> {
-        let path = self.account_path(account_key);
+    /// Encrypt and save an account to disk
+    async fn save_to_disk(&self, account_key: &str, account: &Account) -> Result<()> {
+        let key_manager = self.key_manager.read().await;
+        write_account_file(
+            &self.account_path(account_key),
+            key_manager.key(),
+            account_key,
+            account,
+        )
+        .await
+    }
 
-        // Serialize session
-        let plaintext = serde_json::to_vec(session).map_err(|e| {
-            RcAuthError::InvalidResponse(format!("Failed to serialize session: {}", e))
-        })?;
+    /// List account files without taking the storage lock
+    async fn list_account_keys(&self) -> Vec<String> {
+        let mut accounts = Vec::new();
 
-        // Encrypt
-        let key_manager = self.key_manager.read().await;
-        let encrypted = crypto::encrypt(key_manager.key(), &plaintext, account_key)?;
+        let mut entries = match fs::read_dir(&self.accounts_dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::error!("Failed to read accounts directory: {}", e);
+                return accounts;
+            }
+        };
 
-        // Serialize encrypted blob
-        let encrypted_json = serde_json::to_string_pretty(&encrypted).map_err(|e| {
-            RcAuthError::InvalidResponse(format!("Failed to serialize encrypted blob: {}", e))
-        })?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("json")
+                && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+            {
+                accounts.push(stem.to_string());
+            }
+        }
 
-        // Atomic write: write to temp file, then rename
-        let temp_path = path.with_extension("tmp");
-        fs::write(&temp_path, encrypted_json).await?;
+        accounts
+    }
 
-        // Sync to disk
-        let file = std::fs::File::open(&temp_path)?;
-        file.sync_all()?;
+    async fn cache_account(&self, account_key: &str, account: Account, stamp: FileStamp) {
+        self.cache
+            .write()
+            .await
+            .insert(account_key.to_string(), CachedAccount { account, stamp });
+    }
 
-        // Atomic rename
-        fs::rename(&temp_path, &path).await?;
+    /// Export accounts into a passphrase-protected bundle file
+    ///
+    /// The bundle can be imported into a store on another machine with
+    /// [`FileTokenStore::import_accounts`].
+    pub async fn export_accounts(
+        &self,
+        account_keys: &[&str],
+        path: impl AsRef<Path>,
+        passphrase: &str,
+    ) -> Result<()> {
+        let mut accounts = Vec::with_capacity(account_keys.len());
+        for &account_key in account_keys {
+            let account = self
+                .load_account(account_key)
+                .await
+                .ok_or_else(|| RcAuthError::AccountNotFound(account_key.to_string()))?;
+            accounts.push(account);
+        }
+
+        let bundle = AccountBundle::seal(&accounts, passphrase)?;
+        let bundle_json = serde_json::to_string_pretty(&bundle)?;
+
+        // Atomic write: write to temp file, then rename
+        let path = path.as_ref();
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, bundle_json).await?;
 
-        // Set secure permissions (Unix only)
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
             let perms = std::fs::Permissions::from_mode(0o600);
-            std::fs::set_permissions(&path, perms)?;
+            std::fs::set_permissions(&temp_path, perms)?;
         }
 
+        fs::rename(&temp_path, path).await?;
         Ok(())
     }
 
+    /// Import the accounts of a bundle file, replacing accounts with the same key
+    ///
+    /// A Microsoft session is kept when the local copy was refreshed more
+    /// recently than the one in the bundle.
+    pub async fn import_accounts(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+    ) -> Result<ImportSummary> {
+        let content = fs::read_to_string(path).await?;
+        let bundle: AccountBundle = serde_json::from_str(&content)
+            .map_err(|e| RcAuthError::InvalidResponse(format!("Invalid bundle: {}", e)))?;
+
+        let mut summary = ImportSummary::default();
+        for account in bundle.open(passphrase)? {
+            let account_key = account.account_key().to_string();
+            if let Some(local) = self.load_account(&account_key).await
+                && is_newer(&local, &account)
+            {
+                tracing::info!("Keeping newer local session for {}", account_key);
+                summary.skipped.push(account_key);
+                continue;
+            }
+            self.save_account(&account_key, &account).await?;
+            summary.imported.push(account_key);
+        }
+
+        Ok(summary)
+    }
+
     /// Rotate encryption key and re-encrypt all sessions
+    ///
+    /// Accounts are first staged under the new key in `rotation/`, next to a
+    /// journal. Only then is the key committed and the staged files swapped in,
+    /// so a crash at any point is finished or undone by the next
+    /// [`FileTokenStore::new`].
     pub async fn rotate_key(&self) -> Result<()> {
+        let pending = self.key_manager.read().await.prepare_rotation().await?;
+        self.rotate_to(pending).await
+    }
+
+    /// Where the encryption key comes from
+    pub async fn key_mode(&self) -> KeyMode {
+        self.key_manager.read().await.mode()
+    }
+
+    /// Change the passphrase and re-encrypt all sessions
+    ///
+    /// Returns `RcAuthError::WrongPassphrase` if `old_passphrase` is wrong, or
+    /// `RcAuthError::WrongKeyMode` if the store is in keyring mode.
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<()> {
+        let pending = self
+            .key_manager
+            .read()
+            .await
+            .prepare_passphrase_change(old_passphrase, new_passphrase)?;
+        self.rotate_to(pending).await
+    }
+
+    /// Move from a passphrase-derived key to a random key kept only in the OS keyring
+    pub async fn switch_to_keyring(&self) -> Result<()> {
+        let pending = self.key_manager.read().await.prepare_switch_to_keyring()?;
+        self.rotate_to(pending).await
+    }
+
+    /// Move from a keyring-only key to one derived from `passphrase`
+    pub async fn switch_to_passphrase(&self, passphrase: &str) -> Result<()> {
+        let pending = self
+            .key_manager
+            .read()
+            .await
+            .prepare_switch_to_passphrase(passphrase)?;
+        self.rotate_to(pending).await
+    }
+
+    /// Re-encrypt all sessions under `pending` and commit it
+    async fn rotate_to(&self, pending: PendingKey) -> Result<()> {
         let _lock = self.acquire_lock().await?;
 
-        // Load all sessions with current key
-        let account_keys = self.list_accounts().await;
-        let mut sessions = Vec::new();
+        let result = self.rotate_key_journaled(pending).await;
+        if let Err(e) = &result {
+            tracing::error!("Key rotation failed: {}", e);
+            if let Err(e) = self.recover_rotation().await {
+                tracing::error!("Failed to recover from interrupted key rotation: {}", e);
+            }
+        }
+
+        // Clear cache
+        self.cache.write().await.clear();
+
+        result?;
+        let _ = self.events.send(StoreEvent::Rotated);
+        Ok(())
+    }
 
-        for key in &account_keys {
-            if let Some(session) = self.load_from_disk(key).await? {
-                sessions.push((key.clone(), session));
+    /// Run the rotation steps; the caller holds the lock
+    async fn rotate_key_journaled(&self, pending: PendingKey) -> Result<()> {
+        // Load all accounts with current key
+        let mut accounts = Vec::new();
+        for key in self.list_account_keys().await {
+            if let Some(account) = self.load_from_disk(&key).await? {
+                accounts.push((key, account));
             }
         }
 
-        // Rotate key
+        // Journal first, so recovery knows which staged files belong to this rotation
+        fs::create_dir_all(&self.rotation_dir).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let perms = std::fs::Permissions::from_mode(0o700);
+            std::fs::set_permissions(&self.rotation_dir, perms)?;
+        }
+
+        let journal = RotationJournal {
+            started_at: chrono::Utc::now(),
+            account_keys: accounts.iter().map(|(key, _)| key.clone()).collect(),
+            meta: pending.meta().clone(),
+        };
+        let journal_json = serde_json::to_string_pretty(&journal)?;
+        let journal_path = self.rotation_dir.join("journal.json");
+        let temp_path = journal_path.with_extension("tmp");
+        fs::write(&temp_path, journal_json).await?;
+        std::fs::File::open(&temp_path)?.sync_all()?;
+        fs::rename(&temp_path, &journal_path).await?;
+
+        // Stage every account under the new key
+        for (key, account) in &accounts {
+            write_account_file(&self.staged_path(key), pending.key(), key, account).await?;
+            self.fail_point(RotationStep::PartiallyStaged)?;
+        }
+        self.fail_point(RotationStep::Staged)?;
+
+        // Commit the new key; from here on the staged files are the live data
         let mut key_manager = self.key_manager.write().await;
-        key_manager.rotate(&self.storage_dir).await?;
+        #[cfg(test)]
+        {
+            key_manager.fail_before_meta = self.fault_at(RotationStep::KeySaved);
+        }
+        key_manager
+            .commit_rotation(&self.storage_dir, pending)
+            .await?;
         drop(key_manager);
+        self.fail_point(RotationStep::Committed)?;
 
-        // Re-encrypt all sessions with new key
-        for (key, session) in sessions {
-            self.save_to_disk(&key, &session).await?;
+        for (key, _) in &accounts {
+            fs::rename(self.staged_path(key), self.account_path(key)).await?;
+            self.fail_point(RotationStep::Swapping)?;
         }
 
-        // Clear cache
-        self.cache.write().await.clear();
+        fs::remove_dir_all(&self.rotation_dir).await?;
+        Ok(())
+    }
+
+    /// Finish or undo a key rotation left behind by a crash
+    async fn resume_rotation(&self) -> Result<()> {
+        if !self.rotation_dir.exists() {
+            return Ok(());
+        }
+
+        let _lock = self.acquire_lock().await?;
+        self.recover_rotation().await
+    }
+
+    /// Recover from an interrupted rotation; the caller holds the lock
+    ///
+    /// A staged file that decrypts with the current key means the new key was
+    /// committed, so the remaining staged files are swapped in. An account file
+    /// that decrypts means it wasn't, so the staged files are discarded. If
+    /// neither does, the store is reported as corrupted and the journal is
+    /// kept so recovery can be retried.
+    async fn recover_rotation(&self) -> Result<()> {
+        let journal_path = self.rotation_dir.join("journal.json");
+        if !journal_path.exists() {
+            // Crashed before the journal was written: nothing was staged
+            if self.rotation_dir.exists() {
+                fs::remove_dir_all(&self.rotation_dir).await?;
+            }
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&journal_path).await?;
+        let journal: RotationJournal = serde_json::from_str(&content).map_err(|e| {
+            RcAuthError::InvalidResponse(format!("Invalid rotation journal: {}", e))
+        })?;
+
+        // Accounts not swapped in yet
+        let staged: Vec<&String> = journal
+            .account_keys
+            .iter()
+            .filter(|key| self.staged_path(key).exists())
+            .collect();
+
+        let mut key_manager = self.key_manager.write().await;
 
+        let mut committed = None;
+        for key in &staged {
+            if decrypts_with(&self.staged_path(key), key_manager.key(), key).await {
+                committed = Some(true);
+                break;
+            }
+            if decrypts_with(&self.account_path(key), key_manager.key(), key).await {
+                committed = Some(false);
+                break;
+            }
+        }
+
+        match committed {
+            Some(true) => {
+                tracing::info!("Resuming interrupted key rotation");
+                for key in staged {
+                    fs::rename(self.staged_path(key), self.account_path(key)).await?;
+                }
+                // The keyring may have been updated without meta.json
+                key_manager
+                    .restore_meta(&self.storage_dir, journal.meta)
+                    .await?;
+            }
+            Some(false) => {
+                tracing::info!("Rolling back interrupted key rotation");
+            }
+            // Nothing left to swap: every account was swapped in, or none was staged
+            None if staged.is_empty() => {}
+            None => {
+                tracing::error!("No account file decrypts during key rotation recovery");
+                return Err(RcAuthError::CorruptedStore);
+            }
+        }
+
+        fs::remove_dir_all(&self.rotation_dir).await?;
         Ok(())
     }
+
+    /// Path of an account file staged during key rotation
+    fn staged_path(&self, account_key: &str) -> PathBuf {
+        self.rotation_dir.join(format!("{}.json", account_key))
+    }
+
+    /// Simulate a failure at `step` if a test asked for one
+    #[cfg(test)]
+    fn fail_point(&self, step: RotationStep) -> Result<()> {
+        match self.fault_at(step) {
+            Some(fault) => fault.trigger(step),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(test))]
+    fn fail_point(&self, _step: RotationStep) -> Result<()> {
+        Ok(())
+    }
+
+    /// Failure a test asked for at `step`
+    #[cfg(test)]
+    fn fault_at(&self, step: RotationStep) -> Option<Fault> {
+        self.fail_at
+            .lock()
+            .unwrap()
+            .filter(|(at, _)| *at == step)
+            .map(|(_, fault)| fault)
+    }
+}
+
+/// Whether `local` holds newer tokens than `imported`
+fn is_newer(local: &Account, imported: &Account) -> bool {
+    match (local, imported) {
+        (Account::Microsoft(local), Account::Microsoft(imported)) => {
+            local.ms.expires_at > imported.ms.expires_at
+        }
+        _ => false,
+    }
+}
+
+/// Encrypt an account under `key` and atomically write it to `path`
+async fn write_account_file(
+    path: &Path,
+    key: &EncryptionKey,
+    account_key: &str,
+    account: &Account,
+) -> Result<()> {
+    // Serialize account
+    let plaintext = serde_json::to_vec(account)
+        .map_err(|e| RcAuthError::InvalidResponse(format!("Failed to serialize session: {}", e)))?;
+
+    // Encrypt
+    let encrypted = crypto::encrypt(key, &plaintext, account_key)?;
+
+    // Serialize encrypted blob
+    let encrypted_json = serde_json::to_string_pretty(&encrypted).map_err(|e| {
+        RcAuthError::InvalidResponse(format!("Failed to serialize encrypted blob: {}", e))
+    })?;
+
+    // Atomic write: write to temp file, then rename
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, encrypted_json).await?;
+
+    // Sync to disk
+    let file = std::fs::File::open(&temp_path)?;
+    file.sync_all()?;
+
+    // Atomic rename
+    fs::rename(&temp_path, path).await?;
+
+    // Set secure permissions (Unix only)
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perms = std::fs::Permissions::from_mode(0o600);
+        std::fs::set_permissions(path, perms)?;
+    }
+
+    Ok(())
+}
+
+/// Whether the account file at `path` decrypts with `key`
+async fn decrypts_with(path: &Path, key: &EncryptionKey, account_key: &str) -> bool {
+    let Ok(content) = fs::read_to_string(path).await else {
+        return false;
+    };
+    let Ok(encrypted) = serde_json::from_str::<EncryptedBlob>(&content) else {
+        return false;
+    };
+    crypto::decrypt(key, &encrypted, account_key).is_ok()
 }
 
 #[async_trait::async_trait]
 impl TokenStore for FileTokenStore {
-    async fn load(&self, account_key: &str) -> Option<Session> {
-        // Check cache first
-        {
+    async fn load_account(&self, account_key: &str) -> Option<Account> {
+        let _lock = match self.acquire_shared_lock().await {
+            Ok(lock) => lock,
+            Err(e) => {
+                tracing::error!("Failed to lock storage: {}", e);
+                return None;
+            }
+        };
+
+        let stamp = match FileStamp::read(&self.account_path(account_key)).await {
+            Ok(stamp) => stamp,
+            Err(e) => {
+                tracing::error!("Failed to stat session for {}: {}", account_key, e);
+                return None;
+            }
+        };
+
+        // Removed by another process
+        let Some(stamp) = stamp else {
+            if self.cache.write().await.remove(account_key).is_some() {
+                let _ = self.events.send(StoreEvent::Removed {
+                    account_key: account_key.to_string(),
+                });
+            }
+            return None;
+        };
+
+        // Use the cache only if the file hasn't been rewritten since
+        let rewritten = {
             let cache = self.cache.read().await;
-            if let Some(session) = cache.get(account_key) {
-                return Some(session.clone());
+            match cache.get(account_key) {
+                Some(cached) if cached.stamp == stamp => return Some(cached.account.clone()),
+                cached => cached.is_some(),
             }
-        }
+        };
 
         // Load from disk
         match self.load_from_disk(account_key).await {
-            Ok(Some(session)) => {
-                // Update cache
-                self.cache
-                    .write()
-                    .await
-                    .insert(account_key.to_string(), session.clone());
-                Some(session)
+            Ok(Some(account)) => {
+                // Update cache with the stamp taken before reading: if the file
+                // changed in between, the next load simply reads it again
+                self.cache_account(account_key, account.clone(), stamp)
+                    .await;
+                // Saved by another process
+                if rewritten {
+                    let _ = self.events.send(StoreEvent::Saved {
+                        account_key: account_key.to_string(),
+                    });
+                }
+                Some(account)
             }
             Ok(None) => None,
             Err(e) => {
@@ -230,21 +877,48 @@ impl TokenStore for FileTokenStore {
         }
     }
 
-    async fn save(&self, account_key: &str, session: &Session) -> Result<()> {
+    async fn save_account(&self, account_key: &str, account: &Account) -> Result<()> {
         let _lock = self.acquire_lock().await?;
 
         // Save to disk
-        self.save_to_disk(account_key, session).await?;
+        self.save_to_disk(account_key, account).await?;
 
-        // Update cache
-        self.cache
-            .write()
-            .await
-            .insert(account_key.to_string(), session.clone());
+        // Update cache; the file can't change under us while we hold the lock
+        if let Some(stamp) = FileStamp::read(&self.account_path(account_key)).await? {
+            self.cache_account(account_key, account.clone(), stamp)
+                .await;
+        }
 
+        let _ = self.events.send(StoreEvent::Saved {
+            account_key: account_key.to_string(),
+        });
         Ok(())
     }
 
+    async fn update_account(
+        &self,
+        account_key: &str,
+        update: AccountUpdate,
+    ) -> Result<Option<Account>> {
+        let _lock = self.acquire_lock().await?;
+
+        let Some(mut account) = self.load_from_disk(account_key).await? else {
+            return Ok(None);
+        };
+        update(&mut account);
+        self.save_to_disk(account_key, &account).await?;
+
+        if let Some(stamp) = FileStamp::read(&self.account_path(account_key)).await? {
+            self.cache_account(account_key, account.clone(), stamp)
+                .await;
+        }
+
+        let _ = self.events.send(StoreEvent::Saved {
+            account_key: account_key.to_string(),
+        });
+        Ok(Some(account))
+    }
+
     async fn remove(&self, account_key: &str) -> Result<()> {
         let _lock = self.acquire_lock().await?;
 
@@ -257,30 +931,28 @@ impl TokenStore for FileTokenStore {
         // Remove from cache
         self.cache.write().await.remove(account_key);
 
+        let _ = self.events.send(StoreEvent::Removed {
+            account_key: account_key.to_string(),
+        });
         Ok(())
     }
 
     async fn list_accounts(&self) -> Vec<String> {
-        let mut accounts = Vec::new();
-
-        let mut entries = match fs::read_dir(&self.accounts_dir).await {
-            Ok(entries) => entries,
+        let _lock = match self.acquire_shared_lock().await {
+            Ok(lock) => lock,
             Err(e) => {
-                tracing::error!("Failed to read accounts directory: {}", e);
-                return accounts;
+                tracing::error!("Failed to lock storage: {}", e);
+                return Vec::new();
             }
         };
 
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json")
-                && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
-            {
-                accounts.push(stem.to_string());
-            }
-        }
+        self.list_account_keys().await
+    }
 
-        accounts
+    /// Changes written by another store on the same directory are reported
+    /// once this store loads an account it had cached
+    fn subscribe(&self) -> Option<broadcast::Receiver<StoreEvent>> {
+        Some(self.events.subscribe())
     }
 }
 
@@ -328,6 +1000,8 @@ mod tests {
             },
             xuid: None,
             gamertag: None,
+            entitlements: None,
+            certificates: None,
         };
 
         // Save
@@ -367,6 +1041,8 @@ mod tests {
             },
             xuid: None,
             gamertag: None,
+            entitlements: None,
+            certificates: None,
         };
 
         store.save("test-uuid", &session).await.unwrap();
@@ -405,6 +1081,8 @@ mod tests {
                 },
                 xuid: None,
                 gamertag: None,
+                entitlements: None,
+                certificates: None,
             };
 
             store.save(&format!("uuid-{}", i), &session).await.unwrap();
@@ -413,4 +1091,1026 @@ mod tests {
         let accounts = store.list_accounts().await;
         assert_eq!(accounts.len(), 3);
     }
+
+    #[tokio::test]
+    async fn test_offline_and_microsoft_accounts() {
+        let (store, _temp) = create_test_store().await;
+
+        use crate::account::{Account, OfflineAccount, UserType};
+        use crate::models::McProfile;
+        use crate::session::*;
+
+        let session = Session {
+            ms: MsTokens::new("token".to_string(), None, 3600),
+            xbl: XblToken {
+                token: "xbl".to_string(),
+                uhs: "uhs".to_string(),
+                not_after: None,
+            },
+            xsts: XstsToken {
+                token: "xsts".to_string(),
+                uhs: "uhs".to_string(),
+                not_after: None,
+            },
+            mc: McToken::new("mc".to_string(), 3600),
+            profile: McProfile {
+                id: "ms-uuid".to_string(),
+                name: "MsPlayer".to_string(),
+                skins: vec![],
+                capes: vec![],
+            },
+            xuid: None,
+            gamertag: None,
+            entitlements: None,
+            certificates: None,
+        };
+        let offline = Account::from(OfflineAccount::new("Steve").unwrap());
+
+        store.save("ms-uuid", &session).await.unwrap();
+        store
+            .save_account(offline.account_key(), &offline)
+            .await
+            .unwrap();
+
+        let mut accounts = store.list_accounts().await;
+        accounts.sort();
+        let mut expected = vec!["ms-uuid".to_string(), offline.account_key().to_string()];
+        expected.sort();
+        assert_eq!(accounts, expected);
+
+        let loaded = store.load_account(offline.account_key()).await.unwrap();
+        assert_eq!(loaded.user_type(), UserType::Local);
+        assert_eq!(loaded.name(), "Steve");
+        assert!(store.load(offline.account_key()).await.is_none());
+
+        let loaded = store.load_account("ms-uuid").await.unwrap();
+        assert_eq!(loaded.user_type(), UserType::Msa);
+        assert_eq!(loaded.as_session(), Some(&session));
+    }
+
+    #[tokio::test]
+    async fn test_player_certificates_are_encrypted() {
+        let (store, temp) = create_test_store().await;
+
+        use crate::client::tests::test_session;
+        use crate::session::PlayerCertificates;
+
+        let mut session = test_session();
+        session.certificates = Some(PlayerCertificates {
+            private_key: "PRIVATE-KEY-PEM".to_string(),
+            public_key: "PUBLIC-KEY-PEM".to_string(),
+            public_key_signature: "sig".to_string(),
+            public_key_signature_v2: Some("sig-v2".to_string()),
+            expires_at: chrono::Utc::now() + chrono::Duration::hours(48),
+            refreshed_after: chrono::Utc::now() + chrono::Duration::hours(40),
+        });
+        store.save(session.account_key(), &session).await.unwrap();
+
+        let raw = std::fs::read_to_string(store.account_path(session.account_key())).unwrap();
+        assert!(!raw.contains("PRIVATE-KEY-PEM"));
+
+        // Reopen so the session is read back from disk
+        let secret_provider = Arc::new(StaticSecretProvider::new("test-passphrase"));
+        let reopened = FileTokenStore::new(temp.path(), secret_provider)
+            .await
+            .unwrap();
+        let loaded = reopened.load(session.account_key()).await.unwrap();
+        assert_eq!(loaded.certificates, session.certificates);
+    }
+
+    #[tokio::test]
+    async fn test_store_events() {
+        let (store, _temp) = create_test_store().await;
+        let mut events = store.subscribe().unwrap();
+
+        let offline = Account::from(crate::account::OfflineAccount::new("Steve").unwrap());
+        let key = offline.account_key().to_string();
+
+        store.save_account(&key, &offline).await.unwrap();
+        store.rotate_key().await.unwrap();
+        store.remove(&key).await.unwrap();
+
+        assert_eq!(
+            events.recv().await.unwrap(),
+            StoreEvent::Saved {
+                account_key: key.clone()
+            }
+        );
+        assert_eq!(events.recv().await.unwrap(), StoreEvent::Rotated);
+        assert_eq!(
+            events.recv().await.unwrap(),
+            StoreEvent::Removed { account_key: key }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_two_stores_see_each_others_writes() {
+        let (first, temp) = create_test_store().await;
+        let second = FileTokenStore::new(
+            temp.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await
+        .unwrap();
+
+        use crate::client::tests::test_session;
+
+        let mut session = test_session();
+        let key = session.account_key().to_string();
+        first.save(&key, &session).await.unwrap();
+
+        // Second store caches the original refresh token
+        let loaded = second.load(&key).await.unwrap();
+        assert_eq!(loaded.ms.refresh_token.as_deref(), Some("ms-refresh"));
+
+        // First store rotates it, twice to rule out same-size/same-mtime rewrites
+        for refresh_token in ["rotated-1", "rotated-2"] {
+            session.ms.refresh_token = Some(refresh_token.to_string());
+            first.save(&key, &session).await.unwrap();
+
+            let loaded = second.load(&key).await.unwrap();
+            assert_eq!(loaded.ms.refresh_token.as_deref(), Some(refresh_token));
+        }
+
+        // And the other way around
+        session.ms.refresh_token = Some("from-second".to_string());
+        second.save(&key, &session).await.unwrap();
+        let loaded = first.load(&key).await.unwrap();
+        assert_eq!(loaded.ms.refresh_token.as_deref(), Some("from-second"));
+
+        first.remove(&key).await.unwrap();
+        assert!(second.load(&key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_events_for_writes_by_other_store() {
+        let (first, temp) = create_test_store().await;
+        let second = FileTokenStore::new(
+            temp.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await
+        .unwrap();
+        let mut events = second.subscribe().unwrap();
+
+        use crate::client::tests::test_session;
+
+        let mut session = test_session();
+        let key = session.account_key().to_string();
+        first.save(&key, &session).await.unwrap();
+        second.load(&key).await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        session.ms.refresh_token = Some("rotated".to_string());
+        first.save(&key, &session).await.unwrap();
+        second.load(&key).await.unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            StoreEvent::Saved {
+                account_key: key.clone()
+            }
+        );
+
+        // Reported once, not on every load
+        second.load(&key).await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        first.remove(&key).await.unwrap();
+        assert!(second.load(&key).await.is_none());
+        assert!(second.load(&key).await.is_none());
+        assert_eq!(
+            events.try_recv().unwrap(),
+            StoreEvent::Removed { account_key: key }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    /// Lock the store's lock file from "another process"
+    fn hold_lock(dir: &Path, exclusive: bool) -> std::fs::File {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(dir.join("lock"))
+            .unwrap();
+        if exclusive {
+            FileExt::lock_exclusive(&file).unwrap();
+        } else {
+            FileExt::lock_shared(&file).unwrap();
+        }
+        file
+    }
+
+    #[tokio::test]
+    async fn test_lock_waits_then_times_out() {
+        let temp = TempDir::new().unwrap();
+        let options = FileStoreOptions {
+            lock_timeout: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let store = FileTokenStore::with_options(
+            temp.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+            options,
+        )
+        .await
+        .unwrap();
+        let offline = Account::from(crate::account::OfflineAccount::new("Steve").unwrap());
+
+        // Released before the timeout: the save waits and succeeds
+        let held = hold_lock(temp.path(), true);
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(held);
+        });
+        store
+            .save_account(offline.account_key(), &offline)
+            .await
+            .unwrap();
+        release.join().unwrap();
+
+        // Never released: the save gives up
+        let _held = hold_lock(temp.path(), true);
+        let started = Instant::now();
+        let result = store.save_account(offline.account_key(), &offline).await;
+        assert!(matches!(result, Err(RcAuthError::LockTimeout)));
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_reads_share_the_lock() {
+        let (store, temp) = create_test_store().await;
+        let offline = Account::from(crate::account::OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(offline.account_key(), &offline)
+            .await
+            .unwrap();
+
+        // Another reader holds a shared lock: reads go through, writes wait
+        let _reader = hold_lock(temp.path(), false);
+        let (loaded, listed) = tokio::join!(
+            store.load_account(offline.account_key()),
+            store.list_accounts()
+        );
+        assert_eq!(loaded, Some(offline.clone()));
+        assert_eq!(listed, vec![offline.account_key().to_string()]);
+    }
+
+    /// Session JSON as written by format 1, before accounts were tagged and
+    /// before `entitlements`/`certificates` existed
+    fn legacy_session_json() -> serde_json::Value {
+        serde_json::json!({
+            "ms": {
+                "access_token": "ms",
+                "refresh_token": "legacy-refresh",
+                "expires_at": "2099-01-01T00:00:00Z"
+            },
+            "xbl": { "token": "xbl", "uhs": "uhs", "not_after": "2099-01-01T00:00:00.0000000Z" },
+            "xsts": { "token": "xsts", "uhs": "uhs", "not_after": null },
+            "mc": { "access_token": "mc", "expires_at": "2099-01-01T00:00:00Z" },
+            "profile": { "id": "legacy-uuid", "name": "Legacy", "skins": [], "capes": [] },
+            "xuid": "2535400000000000"
+        })
+    }
+
+    fn set_meta_version(dir: &Path, version: u32) {
+        let path = dir.join("meta.json");
+        let mut meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        meta["version"] = version.into();
+        std::fs::write(&path, meta.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_migrates_v1_store_with_backup() {
+        let (store, temp) = create_test_store().await;
+
+        // Write a format 1 store: bare session payload, meta version 1
+        let plaintext = serde_json::to_vec(&legacy_session_json()).unwrap();
+        let blob = {
+            let key_manager = store.key_manager.read().await;
+            crypto::encrypt(key_manager.key(), &plaintext, "legacy-uuid").unwrap()
+        };
+        std::fs::write(
+            store.account_path("legacy-uuid"),
+            serde_json::to_string(&blob).unwrap(),
+        )
+        .unwrap();
+        drop(store);
+        set_meta_version(temp.path(), 1);
+
+        let store = FileTokenStore::new(
+            temp.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await
+        .unwrap();
+
+        // Upgraded in place: tagged account, new fields defaulted
+        let session = store.load("legacy-uuid").await.unwrap();
+        assert_eq!(session.ms.refresh_token.as_deref(), Some("legacy-refresh"));
+        assert!(!session.xbl.is_expired());
+        assert_eq!(session.entitlements, None);
+        assert_eq!(session.certificates, None);
+
+        let raw = std::fs::read_to_string(store.account_path("legacy-uuid")).unwrap();
+        let blob: EncryptedBlob = serde_json::from_str(&raw).unwrap();
+        let plaintext = {
+            let key_manager = store.key_manager.read().await;
+            crypto::decrypt(key_manager.key(), &blob, "legacy-uuid").unwrap()
+        };
+        let payload: serde_json::Value = serde_json::from_slice(&plaintext).unwrap();
+        assert_eq!(payload["kind"], "microsoft");
+
+        assert_eq!(
+            store.key_manager.read().await.version(),
+            STORE_FORMAT_VERSION
+        );
+
+        // Backup holds the original files
+        let backups: Vec<_> = std::fs::read_dir(temp.path().join("backups"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].join("meta.json").exists());
+        assert!(backups[0].join("accounts/legacy-uuid.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_migration_skips_unreadable_account() {
+        let (store, temp) = create_test_store().await;
+
+        let plaintext = serde_json::to_vec(&legacy_session_json()).unwrap();
+        let blob = {
+            let key_manager = store.key_manager.read().await;
+            crypto::encrypt(key_manager.key(), &plaintext, "legacy-uuid").unwrap()
+        };
+        std::fs::write(
+            store.account_path("legacy-uuid"),
+            serde_json::to_string(&blob).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(store.account_path("broken-uuid"), "not json").unwrap();
+        drop(store);
+        set_meta_version(temp.path(), 1);
+
+        let store = FileTokenStore::new(
+            temp.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await
+        .unwrap();
+
+        assert!(store.load("legacy-uuid").await.is_some());
+        assert_eq!(
+            store.key_manager.read().await.version(),
+            STORE_FORMAT_VERSION
+        );
+
+        // Left in place, with a copy in the backup
+        assert_eq!(
+            std::fs::read_to_string(store.account_path("broken-uuid")).unwrap(),
+            "not json"
+        );
+        let backup = std::fs::read_dir(temp.path().join("backups"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        assert!(backup.join("accounts/broken-uuid.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_refuses_newer_format() {
+        let (store, temp) = create_test_store().await;
+        drop(store);
+        set_meta_version(temp.path(), STORE_FORMAT_VERSION + 1);
+
+        let result = FileTokenStore::new(
+            temp.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(RcAuthError::UnsupportedStoreVersion { found, supported })
+                if found == STORE_FORMAT_VERSION + 1 && supported == STORE_FORMAT_VERSION
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_export_import_between_stores() {
+        let (source, _source_dir) = create_test_store().await;
+        let target_dir = TempDir::new().unwrap();
+        let target = FileTokenStore::new(
+            target_dir.path(),
+            Arc::new(StaticSecretProvider::new("other-passphrase")),
+        )
+        .await
+        .unwrap();
+
+        use crate::account::OfflineAccount;
+        use crate::client::tests::test_session;
+
+        let session = test_session();
+        let steve = Account::from(OfflineAccount::new("Steve").unwrap());
+        let alex = Account::from(OfflineAccount::new("Alex").unwrap());
+        source.save(session.account_key(), &session).await.unwrap();
+        for account in [&steve, &alex] {
+            source
+                .save_account(account.account_key(), account)
+                .await
+                .unwrap();
+        }
+
+        let bundle_path = target_dir.path().join("accounts.rcbundle");
+        source
+            .export_accounts(
+                &[session.account_key(), steve.account_key()],
+                &bundle_path,
+                "bundle-passphrase",
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            target
+                .import_accounts(&bundle_path, "not-the-passphrase")
+                .await,
+            Err(RcAuthError::WrongPassphrase)
+        ));
+
+        let summary = target
+            .import_accounts(&bundle_path, "bundle-passphrase")
+            .await
+            .unwrap();
+        let mut imported = summary.imported;
+        imported.sort();
+        let mut expected = vec![
+            session.account_key().to_string(),
+            steve.account_key().to_string(),
+        ];
+        expected.sort();
+        assert_eq!(imported, expected);
+        assert!(summary.skipped.is_empty());
+
+        assert_eq!(
+            target.load(session.account_key()).await,
+            Some(session.clone())
+        );
+        assert_eq!(
+            target.load_account(steve.account_key()).await,
+            Some(steve.clone())
+        );
+        assert!(target.load_account(alex.account_key()).await.is_none());
+
+        // A session refreshed since the export is kept, an older one replaced
+        let mut refreshed = session.clone();
+        refreshed.ms.access_token = "refreshed-access".to_string();
+        refreshed.ms.expires_at += chrono::Duration::hours(1);
+        target
+            .save(refreshed.account_key(), &refreshed)
+            .await
+            .unwrap();
+
+        let summary = target
+            .import_accounts(&bundle_path, "bundle-passphrase")
+            .await
+            .unwrap();
+        assert_eq!(summary.imported, vec![steve.account_key().to_string()]);
+        assert_eq!(summary.skipped, vec![session.account_key().to_string()]);
+        assert_eq!(target.load(session.account_key()).await, Some(refreshed));
+
+        let mut older = session.clone();
+        older.ms.expires_at -= chrono::Duration::hours(1);
+        target.save(older.account_key(), &older).await.unwrap();
+
+        let summary = target
+            .import_accounts(&bundle_path, "bundle-passphrase")
+            .await
+            .unwrap();
+        assert!(summary.skipped.is_empty());
+        assert_eq!(target.load(session.account_key()).await, Some(session));
+    }
+
+    /// Fail `rotate_key` at `step`, reopen the store and check every account survived
+    ///
+    /// A failure that returns an error must be recovered before `rotate_key`
+    /// returns. Returns whether the reopened store uses the new key.
+    async fn recover_from_failure_at(step: RotationStep, fault: Fault, mode: KeyMode) -> bool {
+        let temp_dir = TempDir::new().unwrap();
+        let secret_provider = Arc::new(StaticSecretProvider::new("test-passphrase"));
+        let store = FileTokenStore::new(temp_dir.path(), secret_provider.clone())
+            .await
+            .unwrap();
+        if mode == KeyMode::Keyring {
+            store.switch_to_keyring().await.unwrap();
+        }
+
+        use crate::account::OfflineAccount;
+
+        let accounts = [
+            Account::from(OfflineAccount::new("Steve").unwrap()),
+            Account::from(OfflineAccount::new("Alex").unwrap()),
+        ];
+        for account in &accounts {
+            store
+                .save_account(account.account_key(), account)
+                .await
+                .unwrap();
+        }
+        let old_check = store.key_manager.read().await.meta().key_check.clone();
+
+        *store.fail_at.lock().unwrap() = Some((step, fault));
+        match fault {
+            Fault::Crash => {
+                let store = Arc::new(store);
+                let rotating = Arc::clone(&store);
+                let crashed = tokio::spawn(async move { rotating.rotate_key().await }).await;
+                assert!(crashed.unwrap_err().is_panic());
+            }
+            Fault::Error => {
+                assert!(store.rotate_key().await.is_err());
+                assert!(!temp_dir.path().join("rotation").exists());
+                for account in &accounts {
+                    assert_eq!(
+                        store.load_account(account.account_key()).await.as_ref(),
+                        Some(account)
+                    );
+                }
+            }
+        }
+
+        let store = FileTokenStore::new(temp_dir.path(), secret_provider)
+            .await
+            .unwrap();
+        assert!(!temp_dir.path().join("rotation").exists());
+        assert_eq!(store.key_mode().await, mode);
+        for account in &accounts {
+            assert_eq!(
+                store.load_account(account.account_key()).await.as_ref(),
+                Some(account)
+            );
+        }
+
+        let new_check = store.key_manager.read().await.meta().key_check.clone();
+        new_check != old_check
+    }
+
+    #[tokio::test]
+    async fn test_rotation_rolls_back_crash_while_staging() {
+        assert!(
+            !recover_from_failure_at(
+                RotationStep::PartiallyStaged,
+                Fault::Crash,
+                KeyMode::Passphrase
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_rolls_back_crash_before_commit() {
+        assert!(
+            !recover_from_failure_at(RotationStep::Staged, Fault::Crash, KeyMode::Passphrase).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_resumes_crash_after_commit() {
+        assert!(
+            recover_from_failure_at(RotationStep::Committed, Fault::Crash, KeyMode::Passphrase)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_resumes_crash_while_swapping() {
+        assert!(
+            recover_from_failure_at(RotationStep::Swapping, Fault::Crash, KeyMode::Passphrase)
+                .await
+        );
+    }
+
+    #[cfg(feature = "keyring-support")]
+    #[tokio::test]
+    async fn test_rotation_recovers_crash_between_keyring_and_meta() {
+        crate::key_manager::test_keyring::enable();
+
+        // The cached key is stale, so the passphrase unlocks the old key
+        assert!(
+            !recover_from_failure_at(RotationStep::KeySaved, Fault::Crash, KeyMode::Passphrase)
+                .await
+        );
+        // The keyring holds the only copy, so the new key is kept
+        assert!(
+            recover_from_failure_at(RotationStep::KeySaved, Fault::Crash, KeyMode::Keyring).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_recovers_failed_step() {
+        for (step, committed) in [
+            (RotationStep::PartiallyStaged, false),
+            (RotationStep::Staged, false),
+            (RotationStep::KeySaved, false),
+            (RotationStep::Committed, true),
+            (RotationStep::Swapping, true),
+        ] {
+            assert_eq!(
+                recover_from_failure_at(step, Fault::Error, KeyMode::Passphrase).await,
+                committed,
+                "{:?}",
+                step
+            );
+        }
+
+        // The previous key is put back in the keyring
+        #[cfg(feature = "keyring-support")]
+        {
+            crate::key_manager::test_keyring::enable();
+            assert!(
+                !recover_from_failure_at(RotationStep::KeySaved, Fault::Error, KeyMode::Keyring)
+                    .await
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_recovery_keeps_journal_when_nothing_decrypts() {
+        let (store, temp_dir) = create_test_store().await;
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+
+        *store.fail_at.lock().unwrap() = Some((RotationStep::Staged, Fault::Crash));
+        let store = Arc::new(store);
+        let rotating = Arc::clone(&store);
+        let crashed = tokio::spawn(async move { rotating.rotate_key().await }).await;
+        assert!(crashed.unwrap_err().is_panic());
+        drop(store);
+
+        let file_name = format!("{}.json", account.account_key());
+        std::fs::write(temp_dir.path().join("accounts").join(&file_name), "{}").unwrap();
+        std::fs::write(temp_dir.path().join("rotation").join(&file_name), "{}").unwrap();
+
+        let reopened = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await;
+        assert!(matches!(reopened, Err(RcAuthError::CorruptedStore)));
+        assert!(temp_dir.path().join("rotation/journal.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_change_passphrase() {
+        let (store, temp_dir) = create_test_store().await;
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store
+                .change_passphrase("wrong-passphrase", "new-passphrase")
+                .await,
+            Err(RcAuthError::WrongPassphrase)
+        ));
+        store
+            .change_passphrase("test-passphrase", "new-passphrase")
+            .await
+            .unwrap();
+        drop(store);
+        assert!(!temp_dir.path().join("meta.json.tmp").exists());
+
+        let store = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("new-passphrase")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(store.key_mode().await, KeyMode::Passphrase);
+        assert_eq!(
+            store.load_account(account.account_key()).await,
+            Some(account)
+        );
+    }
+
+    #[cfg(feature = "keyring-support")]
+    #[tokio::test]
+    async fn test_switch_key_mode() {
+        crate::key_manager::test_keyring::enable();
+        let (store, temp_dir) = create_test_store().await;
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store.switch_to_passphrase("other-passphrase").await,
+            Err(RcAuthError::WrongKeyMode(KeyMode::Keyring))
+        ));
+
+        store.switch_to_keyring().await.unwrap();
+        assert_eq!(store.key_mode().await, KeyMode::Keyring);
+        let meta = std::fs::read_to_string(temp_dir.path().join("meta.json")).unwrap();
+        assert!(meta.contains(r#""mode": "keyring""#));
+        assert!(!meta.contains("passphrase_salt"));
+        drop(store);
+
+        // The key comes back from the keyring, the passphrase isn't used
+        let store = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("unused-passphrase")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(store.key_mode().await, KeyMode::Keyring);
+        assert_eq!(
+            store.load_account(account.account_key()).await.as_ref(),
+            Some(&account)
+        );
+
+        assert!(matches!(
+            store
+                .change_passphrase("test-passphrase", "new-passphrase")
+                .await,
+            Err(RcAuthError::WrongKeyMode(KeyMode::Passphrase))
+        ));
+
+        store
+            .switch_to_passphrase("other-passphrase")
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("other-passphrase")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(store.key_mode().await, KeyMode::Passphrase);
+        assert_eq!(
+            store.load_account(account.account_key()).await,
+            Some(account)
+        );
+    }
+
+    #[cfg(feature = "keyring-support")]
+    #[tokio::test]
+    async fn test_opens_keyring_store_with_legacy_entry() {
+        use crate::key_manager::test_keyring;
+
+        test_keyring::enable();
+        let (store, temp_dir) = create_test_store().await;
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+        store.switch_to_keyring().await.unwrap();
+        drop(store);
+
+        // As written by older versions: shared entry, no mode or key check
+        test_keyring::move_to_legacy_entry(temp_dir.path());
+        let meta_path = temp_dir.path().join("meta.json");
+        let mut meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&meta_path).unwrap()).unwrap();
+        let fields = meta.as_object_mut().unwrap();
+        fields.remove("mode");
+        fields.remove("key_check");
+        std::fs::write(&meta_path, meta.to_string()).unwrap();
+
+        let store = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("unused-passphrase")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(store.key_mode().await, KeyMode::Keyring);
+        assert_eq!(
+            store.load_account(account.account_key()).await,
+            Some(account)
+        );
+        assert!(test_keyring::has_store_entry(temp_dir.path()));
+    }
+
+    #[cfg(feature = "keyring-support")]
+    #[tokio::test]
+    async fn test_switch_to_keyring_requires_persistent_keyring() {
+        // The default mock keyring forgets keys once the entry is dropped
+        let (store, temp_dir) = create_test_store().await;
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store.switch_to_keyring().await,
+            Err(RcAuthError::Keyring(_))
+        ));
+        assert_eq!(store.key_mode().await, KeyMode::Passphrase);
+        drop(store);
+
+        let store = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            store.load_account(account.account_key()).await,
+            Some(account)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_passphrase_fails_at_unlock() {
+        let (store, temp_dir) = create_test_store().await;
+        drop(store);
+
+        let result = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("wrong-passphrase")),
+        )
+        .await;
+        assert!(matches!(result, Err(RcAuthError::WrongPassphrase)));
+    }
+
+    #[tokio::test]
+    async fn test_kdf_params_upgrade_on_unlock() {
+        let temp_dir = TempDir::new().unwrap();
+        let secret_provider = Arc::new(StaticSecretProvider::new("test-passphrase"));
+        let open = |kdf_params| {
+            FileTokenStore::with_options(
+                temp_dir.path(),
+                secret_provider.clone(),
+                FileStoreOptions {
+                    kdf_params,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let light = KdfParams {
+            memory_kib: 8192,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let store = open(Some(light)).await.unwrap();
+        let meta = store.key_manager.read().await.meta().clone();
+        assert_eq!(meta.kdf, light);
+        assert!(meta.key_check.is_some());
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = open(Some(KdfParams::low_memory())).await.unwrap();
+        let upgraded = store.key_manager.read().await.meta().clone();
+        assert_eq!(upgraded.kdf, KdfParams::low_memory());
+        assert_ne!(upgraded.passphrase_salt, meta.passphrase_salt);
+        assert_eq!(
+            store.load_account(account.account_key()).await.as_ref(),
+            Some(&account)
+        );
+        drop(store);
+
+        // Unlocks under the upgraded parameters without another upgrade, and
+        // default options keep the parameters the store was given
+        for kdf_params in [Some(KdfParams::low_memory()), None] {
+            let store = open(kdf_params).await.unwrap();
+            let meta = store.key_manager.read().await.meta().clone();
+            assert_eq!(meta.kdf, upgraded.kdf);
+            assert_eq!(meta.passphrase_salt, upgraded.passphrase_salt);
+            assert_eq!(
+                store.load_account(account.account_key()).await.as_ref(),
+                Some(&account)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_kdf_upgrade_keeps_store_usable() {
+        let temp_dir = TempDir::new().unwrap();
+        let secret_provider = Arc::new(StaticSecretProvider::new("test-passphrase"));
+        let open = |kdf_params| {
+            FileTokenStore::with_options(
+                temp_dir.path(),
+                secret_provider.clone(),
+                FileStoreOptions {
+                    kdf_params: Some(kdf_params),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let store = open(KdfParams::low_memory()).await.unwrap();
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+        let meta = store.key_manager.read().await.meta().clone();
+        drop(store);
+
+        // An unreadable account makes the re-encryption fail
+        let bad_path = temp_dir.path().join("accounts").join("bad.json");
+        std::fs::write(&bad_path, "not json").unwrap();
+
+        let store = open(KdfParams::default()).await.unwrap();
+        let unchanged = store.key_manager.read().await.meta().clone();
+        assert_eq!(unchanged.kdf, meta.kdf);
+        assert_eq!(unchanged.passphrase_salt, meta.passphrase_salt);
+        assert_eq!(
+            store.load_account(account.account_key()).await.as_ref(),
+            Some(&account)
+        );
+        drop(store);
+
+        // Retried on the next unlock
+        std::fs::remove_file(&bad_path).unwrap();
+        let store = open(KdfParams::default()).await.unwrap();
+        assert_eq!(
+            store.key_manager.read().await.meta().kdf,
+            KdfParams::default()
+        );
+        assert_eq!(
+            store.load_account(account.account_key()).await,
+            Some(account)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_passphrase_does_not_lock_store_without_key_check() {
+        let (store, temp_dir) = create_test_store().await;
+
+        use crate::account::OfflineAccount;
+
+        let account = Account::from(OfflineAccount::new("Steve").unwrap());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+        drop(store);
+
+        // Written before key checks were recorded
+        let meta_path = temp_dir.path().join("meta.json");
+        let mut meta: KeyMeta =
+            serde_json::from_str(&std::fs::read_to_string(&meta_path).unwrap()).unwrap();
+        meta.key_check = None;
+        std::fs::write(&meta_path, serde_json::to_string(&meta).unwrap()).unwrap();
+
+        let result = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("wrong-passphrase")),
+        )
+        .await;
+        assert!(matches!(result, Err(RcAuthError::WrongPassphrase)));
+
+        let store = FileTokenStore::new(
+            temp_dir.path(),
+            Arc::new(StaticSecretProvider::new("test-passphrase")),
+        )
+        .await
+        .unwrap();
+        assert!(store.key_manager.read().await.meta().key_check.is_some());
+        assert_eq!(
+            store.load_account(account.account_key()).await,
+            Some(account)
+        );
+    }
 }
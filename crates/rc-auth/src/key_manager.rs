@@ -7,30 +7,118 @@ use argon2::{
 };
 use base64::Engine;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::fs;
 
-use crate::crypto::EncryptionKey;
+use crate::crypto::{self, EncryptedBlob, EncryptionKey};
 use crate::errors::{RcAuthError, Result};
 use crate::secret::SecretProvider;
 
 const SALT_LEN: usize = 32;
 
+/// Keyring service name for storage keys
+#[cfg(feature = "keyring-support")]
+const KEYRING_SERVICE: &str = "rauncher-mc";
+
+/// Keyring entry shared by every store before entries were scoped per directory
+#[cfg(feature = "keyring-support")]
+const LEGACY_KEYRING_USER: &str = "rc-auth:v1";
+
+/// Current on-disk format version, stored in `meta.json`
+///
+/// - 1: account files may hold a bare `Session`
+/// - 2: every account file holds a tagged `Account`
+pub const STORE_FORMAT_VERSION: u32 = 2;
+
+/// Where the encryption key comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMode {
+    /// Random key kept only in the OS keyring
+    Keyring,
+    /// Key derived from a passphrase with Argon2id, cached in the OS keyring when available
+    Passphrase,
+}
+
+impl std::fmt::Display for KeyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Keyring => write!(f, "keyring"),
+            Self::Passphrase => write!(f, "passphrase"),
+        }
+    }
+}
+
+/// Argon2id cost parameters for passphrase-derived keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Lighter settings for low-memory devices (m=19MB, t=2, p=1)
+    pub fn low_memory() -> Self {
+        Self {
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl Default for KdfParams {
+    /// m=64MB, t=3, p=1, also assumed for stores that didn't record their parameters
+    fn default() -> Self {
+        Self {
+            memory_kib: 65536,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
 /// Metadata for key derivation and storage format
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyMeta {
     pub version: u32,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Key mode; missing in stores written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<KeyMode>,
     /// Base64-encoded salt for Argon2id (if using passphrase)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub passphrase_salt: Option<String>,
+    /// Argon2id parameters used with `passphrase_salt`
+    #[serde(default)]
+    pub kdf: KdfParams,
+    /// Base64-encoded hash of the key, to recognise a wrong passphrase before decrypting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_check: Option<String>,
+}
+
+impl KeyMeta {
+    /// Key mode, inferred from the passphrase salt if it wasn't recorded
+    pub fn mode(&self) -> KeyMode {
+        match self.mode {
+            Some(mode) => mode,
+            None if self.passphrase_salt.is_some() => KeyMode::Passphrase,
+            None => KeyMode::Keyring,
+        }
+    }
 }
 
 impl Default for KeyMeta {
     fn default() -> Self {
         Self {
-            version: 1,
+            version: STORE_FORMAT_VERSION,
             created_at: chrono::Utc::now(),
+            mode: Some(KeyMode::Passphrase),
             passphrase_salt: None,
+            kdf: KdfParams::default(),
+            key_check: None,
         }
     }
 }
@@ -40,99 +128,230 @@ pub struct KeyManager {
     meta: KeyMeta,
     key: EncryptionKey,
     secret_provider: Arc<dyn SecretProvider>,
+    /// Argon2id parameters for new passphrase-derived keys, if set explicitly
+    kdf_params: Option<KdfParams>,
+    /// Key re-derived under `kdf_params`, prepared at unlock
+    kdf_upgrade: Option<PendingKey>,
+    /// Failure to simulate in `commit_rotation` before `meta.json` is written
+    #[cfg(test)]
+    pub(crate) fail_before_meta: Option<Fault>,
 }
 
 impl KeyManager {
-    /// Create a new key manager with OS keyring
-    ///
-    /// Tries to load key from OS keyring first. If not found or keyring unavailable,
-    /// falls back to passphrase-derived key.
-    #[cfg(feature = "keyring-support")]
+    /// Create a new key manager that keeps the store's Argon2id parameters
     pub async fn new(storage_dir: &Path, secret_provider: Arc<dyn SecretProvider>) -> Result<Self> {
-        let meta_path = storage_dir.join("meta.json");
-
-        // Try to load existing metadata
-        let mut meta = if meta_path.exists() {
-            let content = fs::read_to_string(&meta_path).await?;
-            serde_json::from_str(&content)
-                .map_err(|e| RcAuthError::InvalidResponse(format!("Invalid meta.json: {}", e)))?
-        } else {
-            KeyMeta::default()
-        };
-
-        // Try OS keyring first
-        let key = match Self::load_from_keyring() {
-            Ok(key) => {
-                tracing::debug!("Loaded encryption key from OS keyring");
-                key
-            }
-            Err(e) => {
-                tracing::debug!("Keyring unavailable ({}), using passphrase fallback", e);
+        Self::with_params(storage_dir, secret_provider, None).await
+    }
 
-                // Try passphrase fallback
-                let key = Self::derive_from_passphrase(&mut meta, &secret_provider).await?;
+    /// Create a new key manager, deriving new passphrase keys with `kdf_params`
+    ///
+    /// In keyring mode the key must be in the OS keyring. In passphrase mode the
+    /// keyring is tried first, falling back to deriving the key from the passphrase,
+    /// which fails with `RcAuthError::WrongPassphrase` if it doesn't match the
+    /// stored key check. If `kdf_params` is set and the store was derived with
+    /// other parameters, an upgrade is prepared for [`KeyManager::take_kdf_upgrade`].
+    /// Without it, the parameters recorded in the store are kept (the defaults
+    /// for a new store).
+    pub async fn with_params(
+        storage_dir: &Path,
+        secret_provider: Arc<dyn SecretProvider>,
+        kdf_params: Option<KdfParams>,
+    ) -> Result<Self> {
+        let meta_path = storage_dir.join("meta.json");
 
-                // Try to save to keyring for next time
-                if let Err(e) = Self::save_to_keyring(&key) {
-                    tracing::warn!("Failed to save key to keyring: {}", e);
+        let mut meta = Self::read_meta(&meta_path).await?;
+        let mut kdf_upgrade = None;
+
+        let key = match meta.mode() {
+            KeyMode::Keyring => Self::load_keyring_key(storage_dir, &meta).await?,
+            // Try OS keyring first
+            KeyMode::Passphrase => match Self::load_cached_key(storage_dir, &meta).await {
+                Some(key) => key,
+                None => {
+                    // Passphrase fallback
+                    let passphrase = secret_provider
+                        .get_passphrase("Enter passphrase for token storage")
+                        .await
+                        .ok_or(RcAuthError::UserCancelled)?;
+                    let key = Self::derive_from_passphrase(
+                        storage_dir,
+                        &mut meta,
+                        &passphrase,
+                        &kdf_params.unwrap_or_default(),
+                    )
+                    .await?;
+
+                    if let Some(params) = kdf_params
+                        && meta.kdf != params
+                    {
+                        kdf_upgrade = Some(passphrase_key(&meta, &passphrase, &params)?);
+                    }
+
+                    // Try to save to keyring for next time
+                    #[cfg(feature = "keyring-support")]
+                    {
+                        if let Err(e) = Self::save_to_keyring(&keyring_user(storage_dir), &key) {
+                            tracing::warn!("Failed to save key to keyring: {}", e);
+                        }
+                    }
+
+                    key
                 }
-
-                key
-            }
+            },
         };
+        meta.mode = Some(meta.mode());
+
+        // Stores written before key checks existed only get one once the key
+        // is known to be right, or a wrong passphrase would lock them for good
+        if meta.key_check.is_none() && is_store_key(storage_dir, &meta, &key).await {
+            meta.key_check = Some(key_check(&key));
+        }
 
         // Save metadata
-        let meta_json = serde_json::to_string_pretty(&meta).map_err(|e| {
-            RcAuthError::InvalidResponse(format!("Failed to serialize meta: {}", e))
-        })?;
-        fs::write(&meta_path, meta_json).await?;
+        Self::write_meta(&meta_path, &meta).await?;
 
         Ok(Self {
             meta,
             key,
             secret_provider,
+            kdf_params,
+            kdf_upgrade,
+            #[cfg(test)]
+            fail_before_meta: None,
         })
     }
 
-    /// Create a new key manager without keyring support
-    #[cfg(not(feature = "keyring-support"))]
-    pub async fn new(storage_dir: &Path, secret_provider: Arc<dyn SecretProvider>) -> Result<Self> {
-        let meta_path = storage_dir.join("meta.json");
+    /// Key re-derived under the configured Argon2id parameters, if the store needs one
+    ///
+    /// Only available after the passphrase was entered, not when the key came
+    /// from the keyring. Commit it by re-encrypting every account under it.
+    pub fn take_kdf_upgrade(&mut self) -> Option<PendingKey> {
+        self.kdf_upgrade.take()
+    }
 
-        // Try to load existing metadata
-        let mut meta = if meta_path.exists() {
-            let content = fs::read_to_string(&meta_path).await?;
-            serde_json::from_str(&content)
-                .map_err(|e| RcAuthError::InvalidResponse(format!("Invalid meta.json: {}", e)))?
-        } else {
-            KeyMeta::default()
-        };
+    /// Get the encryption key
+    pub fn key(&self) -> &EncryptionKey {
+        &self.key
+    }
 
-        let key = Self::derive_from_passphrase(&mut meta, &secret_provider).await?;
+    /// Where the encryption key comes from
+    pub fn mode(&self) -> KeyMode {
+        self.meta.mode()
+    }
 
-        // Save metadata
-        let meta_json = serde_json::to_string_pretty(&meta).map_err(|e| {
+    /// On-disk format version of the store
+    pub fn version(&self) -> u32 {
+        self.meta.version
+    }
+
+    /// Record that the store has been migrated to `version`
+    pub async fn set_version(&mut self, storage_dir: &Path, version: u32) -> Result<()> {
+        self.meta.version = version;
+        Self::write_meta(&storage_dir.join("meta.json"), &self.meta).await
+    }
+
+    /// Read `meta.json`, refusing formats newer than this build understands
+    async fn read_meta(meta_path: &Path) -> Result<KeyMeta> {
+        if !meta_path.exists() {
+            return Ok(KeyMeta::default());
+        }
+
+        let content = fs::read_to_string(meta_path).await?;
+        let meta: KeyMeta = serde_json::from_str(&content)
+            .map_err(|e| RcAuthError::InvalidResponse(format!("Invalid meta.json: {}", e)))?;
+
+        if meta.version > STORE_FORMAT_VERSION {
+            return Err(RcAuthError::UnsupportedStoreVersion {
+                found: meta.version,
+                supported: STORE_FORMAT_VERSION,
+            });
+        }
+
+        Ok(meta)
+    }
+
+    async fn write_meta(meta_path: &Path, meta: &KeyMeta) -> Result<()> {
+        let meta_json = serde_json::to_string_pretty(meta).map_err(|e| {
             RcAuthError::InvalidResponse(format!("Failed to serialize meta: {}", e))
         })?;
-        fs::write(&meta_path, meta_json).await?;
+        // Write to a temporary file and rename so a crash never leaves a torn meta.json
+        let temp_path = meta_path.with_extension("json.tmp");
+        fs::write(&temp_path, meta_json).await?;
+        std::fs::File::open(&temp_path)?.sync_all()?;
+        fs::rename(&temp_path, meta_path).await?;
+        Ok(())
+    }
 
-        Ok(Self {
-            meta,
-            key,
-            secret_provider,
-        })
+    /// Load the key of a keyring-mode store
+    #[cfg(feature = "keyring-support")]
+    async fn load_keyring_key(storage_dir: &Path, meta: &KeyMeta) -> Result<EncryptionKey> {
+        let user = keyring_user(storage_dir);
+        let key = match Self::load_from_keyring(&user) {
+            Ok(key) => key,
+            // Older versions kept the key of every store in one shared entry
+            Err(e) => match Self::load_from_keyring(LEGACY_KEYRING_USER) {
+                Ok(key) if is_store_key(storage_dir, meta, &key).await => {
+                    if let Err(e) = Self::save_to_keyring(&user, &key) {
+                        tracing::warn!("Failed to save key to keyring: {}", e);
+                    }
+                    key
+                }
+                _ => return Err(e),
+            },
+        };
+        tracing::debug!("Loaded encryption key from OS keyring");
+
+        // Not fatal: a crash during rotation can leave the keyring ahead of
+        // meta.json, which FileTokenStore recovers from
+        if !is_store_key(storage_dir, meta, &key).await {
+            tracing::warn!("Key in OS keyring doesn't match the stored key check");
+        }
+
+        Ok(key)
     }
 
-    /// Get the encryption key
-    pub fn key(&self) -> &EncryptionKey {
-        &self.key
+    #[cfg(not(feature = "keyring-support"))]
+    async fn load_keyring_key(_storage_dir: &Path, _meta: &KeyMeta) -> Result<EncryptionKey> {
+        Err(RcAuthError::Keyring(
+            "Storage uses keyring mode but keyring support is disabled".to_string(),
+        ))
+    }
+
+    /// Key of a passphrase-mode store cached in the OS keyring, if it matches
+    #[cfg(feature = "keyring-support")]
+    async fn load_cached_key(storage_dir: &Path, meta: &KeyMeta) -> Option<EncryptionKey> {
+        let user = keyring_user(storage_dir);
+
+        // Fall back to the shared entry older versions cached every key in
+        for entry_user in [user.as_str(), LEGACY_KEYRING_USER] {
+            match Self::load_from_keyring(entry_user) {
+                Ok(key) if is_store_key(storage_dir, meta, &key).await => {
+                    tracing::debug!("Loaded encryption key from OS keyring");
+                    if entry_user != user
+                        && let Err(e) = Self::save_to_keyring(&user, &key)
+                    {
+                        tracing::warn!("Failed to save key to keyring: {}", e);
+                    }
+                    return Some(key);
+                }
+                Ok(_) => tracing::debug!("Key in OS keyring entry {} is stale", entry_user),
+                Err(e) => tracing::debug!("Keyring entry {} unavailable ({})", entry_user, e),
+            }
+        }
+
+        tracing::debug!("No usable key in OS keyring, using passphrase fallback");
+        None
+    }
+
+    #[cfg(not(feature = "keyring-support"))]
+    async fn load_cached_key(_storage_dir: &Path, _meta: &KeyMeta) -> Option<EncryptionKey> {
+        None
     }
 
     /// Load key from OS keyring
     #[cfg(feature = "keyring-support")]
-    fn load_from_keyring() -> Result<EncryptionKey> {
-        let entry = keyring::Entry::new("rauncher-mc", "rc-auth:v1")
-            .map_err(|e| RcAuthError::Keyring(format!("Failed to access keyring: {}", e)))?;
+    fn load_from_keyring(user: &str) -> Result<EncryptionKey> {
+        let entry = Self::keyring_entry(user)?;
 
         let key_b64 = entry
             .get_password()
@@ -154,9 +373,8 @@ impl KeyManager {
 
     /// Save key to OS keyring
     #[cfg(feature = "keyring-support")]
-    fn save_to_keyring(key: &EncryptionKey) -> Result<()> {
-        let entry = keyring::Entry::new("rauncher-mc", "rc-auth:v1")
-            .map_err(|e| RcAuthError::Keyring(format!("Failed to access keyring: {}", e)))?;
+    fn save_to_keyring(user: &str, key: &EncryptionKey) -> Result<()> {
+        let entry = Self::keyring_entry(user)?;
 
         let key_b64 = base64::engine::general_purpose::STANDARD.encode(key.as_bytes());
 
@@ -167,10 +385,44 @@ impl KeyManager {
         Ok(())
     }
 
-    /// Derive key from passphrase using Argon2id
+    /// Save key to OS keyring and check it reads back through a fresh entry
+    ///
+    /// A non-persistent keyring backend accepts the write but loses the key
+    /// once the entry is dropped.
+    #[cfg(feature = "keyring-support")]
+    fn save_to_keyring_verified(user: &str, key: &EncryptionKey) -> Result<()> {
+        Self::save_to_keyring(user, key)?;
+
+        if Self::load_from_keyring(user)?.as_bytes() != key.as_bytes() {
+            return Err(RcAuthError::Keyring(
+                "Key read back from keyring doesn't match".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "keyring-support")]
+    fn keyring_entry(user: &str) -> Result<keyring::Entry> {
+        #[cfg(test)]
+        {
+            if let Some(entry) = test_keyring::entry(user) {
+                return Ok(entry);
+            }
+        }
+
+        keyring::Entry::new(KEYRING_SERVICE, user)
+            .map_err(|e| RcAuthError::Keyring(format!("Failed to access keyring: {}", e)))
+    }
+
+    /// Derive key from passphrase using Argon2id, checking it against the key check
+    ///
+    /// A store without a salt yet gets a new one, used with `kdf_params`.
     async fn derive_from_passphrase(
+        storage_dir: &Path,
         meta: &mut KeyMeta,
-        secret_provider: &Arc<dyn SecretProvider>,
+        passphrase: &str,
+        kdf_params: &KdfParams,
     ) -> Result<EncryptionKey> {
         // Get or generate salt
         let salt = if let Some(ref salt_b64) = meta.passphrase_salt {
@@ -179,47 +431,18 @@ impl KeyManager {
                 .map_err(|_| RcAuthError::CorruptedStore)?
         } else {
             // Generate new salt using system randomness
-            let mut salt = vec![0u8; SALT_LEN];
-            getrandom::fill(&mut salt)
-                .map_err(|e| RcAuthError::Crypto(format!("Failed to generate salt: {}", e)))?;
+            let salt = generate_salt()?;
             meta.passphrase_salt = Some(base64::engine::general_purpose::STANDARD.encode(&salt));
+            meta.kdf = *kdf_params;
             salt
         };
 
-        // Get passphrase from provider
-        let passphrase = secret_provider
-            .get_passphrase("Enter passphrase for token storage")
-            .await
-            .ok_or(RcAuthError::UserCancelled)?;
-
-        // Derive key using Argon2id
-        // Parameters: m=64MB, t=3, p=1
-        let params = Params::new(65536, 3, 1, Some(32))
-            .map_err(|e| RcAuthError::Crypto(format!("Invalid Argon2 params: {}", e)))?;
-        let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
-
-        let salt_string = SaltString::encode_b64(&salt)
-            .map_err(|e| RcAuthError::Crypto(format!("Invalid salt: {}", e)))?;
-
-        let hash = argon2
-            .hash_password(passphrase.as_bytes(), &salt_string)
-            .map_err(|e| RcAuthError::Crypto(format!("Key derivation failed: {}", e)))?;
-
-        let key_bytes = hash
-            .hash
-            .ok_or_else(|| RcAuthError::Crypto("Argon2 hash returned no output".to_string()))?;
-
-        if key_bytes.len() != 32 {
-            return Err(RcAuthError::Crypto(format!(
-                "Expected 32 bytes, got {}",
-                key_bytes.len()
-            )));
+        let key = derive_key(passphrase, &salt, &meta.kdf)?;
+        if !is_store_key(storage_dir, meta, &key).await {
+            return Err(RcAuthError::WrongPassphrase);
         }
 
-        let mut key = [0u8; 32];
-        key.copy_from_slice(key_bytes.as_bytes());
-
-        Ok(EncryptionKey::from_bytes(key))
+        Ok(key)
     }
 
     /// Rotate the encryption key (re-encrypt all data)
@@ -234,44 +457,313 @@ impl KeyManager {
     /// with the new key. After calling this method, the KeyManager will use the new key
     /// for all subsequent operations.
     pub async fn rotate(&mut self, storage_dir: &Path) -> Result<()> {
-        let new_key = if self.meta.passphrase_salt.is_some() {
-            // Was using passphrase derivation - continue using it with new salt
-            tracing::debug!("Rotating key: deriving from passphrase with new salt");
+        let pending = self.prepare_rotation().await?;
+        self.commit_rotation(storage_dir, pending).await
+    }
 
-            // Clear old salt to force generation of new one
-            self.meta.passphrase_salt = None;
+    /// Generate the next key without persisting it or switching to it
+    ///
+    /// Lets the caller encrypt data under the new key before committing it with
+    /// [`KeyManager::commit_rotation`].
+    pub async fn prepare_rotation(&self) -> Result<PendingKey> {
+        match self.mode() {
+            KeyMode::Passphrase => {
+                // Keep using passphrase derivation, with a new salt
+                tracing::debug!("Rotating key: deriving from passphrase with new salt");
+
+                let passphrase = self
+                    .secret_provider
+                    .get_passphrase("Enter passphrase for token storage")
+                    .await
+                    .ok_or(RcAuthError::UserCancelled)?;
+                self.prepare_passphrase_key(&passphrase)
+            }
+            KeyMode::Keyring => {
+                // Keep using keyring-only - generate new random key
+                tracing::debug!("Rotating key: generating new random key");
+                self.prepare_keyring_key()
+            }
+        }
+    }
 
-            // Derive new key (will generate new salt)
-            Self::derive_from_passphrase(&mut self.meta, &self.secret_provider).await?
-        } else {
-            // Was using keyring-only - generate new random key
-            tracing::debug!("Rotating key: generating new random key");
-            EncryptionKey::generate()
+    /// Prepare a key derived from `new_passphrase`, after checking `old_passphrase`
+    ///
+    /// Returns `RcAuthError::WrongPassphrase` if `old_passphrase` doesn't derive
+    /// the current key.
+    pub fn prepare_passphrase_change(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<PendingKey> {
+        if self.mode() != KeyMode::Passphrase {
+            return Err(RcAuthError::WrongKeyMode(KeyMode::Passphrase));
+        }
+
+        let salt_b64 = self
+            .meta
+            .passphrase_salt
+            .as_ref()
+            .ok_or(RcAuthError::CorruptedStore)?;
+        let salt = base64::engine::general_purpose::STANDARD
+            .decode(salt_b64)
+            .map_err(|_| RcAuthError::CorruptedStore)?;
+        if derive_key(old_passphrase, &salt, &self.meta.kdf)?.as_bytes() != self.key.as_bytes() {
+            return Err(RcAuthError::WrongPassphrase);
+        }
+
+        self.prepare_passphrase_key(new_passphrase)
+    }
+
+    /// Prepare a random keyring-only key, switching away from passphrase mode
+    pub fn prepare_switch_to_keyring(&self) -> Result<PendingKey> {
+        if self.mode() != KeyMode::Passphrase {
+            return Err(RcAuthError::WrongKeyMode(KeyMode::Passphrase));
+        }
+        self.prepare_keyring_key()
+    }
+
+    /// Prepare a key derived from `passphrase`, switching away from keyring mode
+    pub fn prepare_switch_to_passphrase(&self, passphrase: &str) -> Result<PendingKey> {
+        if self.mode() != KeyMode::Keyring {
+            return Err(RcAuthError::WrongKeyMode(KeyMode::Keyring));
+        }
+        self.prepare_passphrase_key(passphrase)
+    }
+
+    /// New passphrase-mode key with a fresh salt
+    fn prepare_passphrase_key(&self, passphrase: &str) -> Result<PendingKey> {
+        passphrase_key(
+            &self.meta,
+            passphrase,
+            &self.kdf_params.unwrap_or(self.meta.kdf),
+        )
+    }
+
+    /// New random keyring-mode key
+    fn prepare_keyring_key(&self) -> Result<PendingKey> {
+        if !cfg!(feature = "keyring-support") {
+            return Err(RcAuthError::Keyring(
+                "Keyring support is disabled".to_string(),
+            ));
+        }
+
+        let key = EncryptionKey::generate();
+        let meta = KeyMeta {
+            created_at: chrono::Utc::now(),
+            mode: Some(KeyMode::Keyring),
+            passphrase_salt: None,
+            key_check: Some(key_check(&key)),
+            ..self.meta.clone()
         };
 
-        // Update metadata timestamp
-        self.meta.created_at = chrono::Utc::now();
+        Ok(PendingKey { key, meta })
+    }
 
-        // Try to save to keyring if available
+    /// Persist a key from [`KeyManager::prepare_rotation`] and start using it
+    pub async fn commit_rotation(&mut self, storage_dir: &Path, pending: PendingKey) -> Result<()> {
+        // Save to keyring before meta.json, so a crash in between leaves the
+        // keyring ahead rather than the passphrase salt
         #[cfg(feature = "keyring-support")]
         {
-            if let Err(e) = Self::save_to_keyring(&new_key) {
+            if let Err(e) = Self::save_to_keyring_verified(&keyring_user(storage_dir), &pending.key)
+            {
+                // In keyring mode the keyring holds the only copy
+                if pending.meta.mode() == KeyMode::Keyring {
+                    return Err(e);
+                }
                 tracing::warn!("Failed to save new key to keyring: {}", e);
             }
         }
 
-        // Save metadata
-        let meta_path = storage_dir.join("meta.json");
-        let meta_json = serde_json::to_string_pretty(&self.meta).map_err(|e| {
-            RcAuthError::InvalidResponse(format!("Failed to serialize meta: {}", e))
-        })?;
-        fs::write(&meta_path, meta_json).await?;
+        // Save metadata, putting the current key back in the keyring on failure
+        let written = async {
+            self.fail_point()?;
+            Self::write_meta(&storage_dir.join("meta.json"), &pending.meta).await
+        }
+        .await;
+        #[cfg(feature = "keyring-support")]
+        {
+            if written.is_err()
+                && let Err(e) = Self::save_to_keyring(&keyring_user(storage_dir), &self.key)
+            {
+                tracing::warn!("Failed to restore key in keyring: {}", e);
+            }
+        }
+        written?;
 
         // Update our key reference
-        self.key = new_key;
+        self.meta = pending.meta;
+        self.key = pending.key;
 
         Ok(())
     }
+
+    /// Replace `meta.json` with the metadata of a key that is already in use
+    ///
+    /// Used to finish a rotation that was interrupted after the key was committed.
+    pub(crate) async fn restore_meta(&mut self, storage_dir: &Path, meta: KeyMeta) -> Result<()> {
+        Self::write_meta(&storage_dir.join("meta.json"), &meta).await?;
+        self.meta = meta;
+        Ok(())
+    }
+
+    /// Metadata of the current key
+    pub fn meta(&self) -> &KeyMeta {
+        &self.meta
+    }
+
+    /// Simulate a failure before `meta.json` is written if a test asked for one
+    #[cfg(test)]
+    fn fail_point(&self) -> Result<()> {
+        match self.fail_before_meta {
+            Some(fault) => fault.trigger("commit before meta.json"),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(test))]
+    fn fail_point(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// New key produced by [`KeyManager::prepare_rotation`], not yet committed
+pub struct PendingKey {
+    key: EncryptionKey,
+    meta: KeyMeta,
+}
+
+impl PendingKey {
+    pub fn key(&self) -> &EncryptionKey {
+        &self.key
+    }
+
+    pub fn meta(&self) -> &KeyMeta {
+        &self.meta
+    }
+}
+
+impl std::fmt::Debug for PendingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingKey")
+            .field("meta", &self.meta)
+            .field("key", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// New passphrase-mode key with a fresh salt, replacing the key described by `meta`
+fn passphrase_key(meta: &KeyMeta, passphrase: &str, kdf_params: &KdfParams) -> Result<PendingKey> {
+    let salt = generate_salt()?;
+    let key = derive_key(passphrase, &salt, kdf_params)?;
+
+    let meta = KeyMeta {
+        created_at: chrono::Utc::now(),
+        mode: Some(KeyMode::Passphrase),
+        passphrase_salt: Some(base64::engine::general_purpose::STANDARD.encode(&salt)),
+        kdf: *kdf_params,
+        key_check: Some(key_check(&key)),
+        ..meta.clone()
+    };
+
+    Ok(PendingKey { key, meta })
+}
+
+/// Whether `key` is the key of the store at `storage_dir`
+///
+/// Compared against the key check when one was recorded. Older stores are
+/// checked by decrypting an account file instead, and accept any key if they
+/// have no accounts yet.
+async fn is_store_key(storage_dir: &Path, meta: &KeyMeta, key: &EncryptionKey) -> bool {
+    if let Some(check) = &meta.key_check {
+        return *check == key_check(key);
+    }
+
+    let Ok(mut entries) = fs::read_dir(storage_dir.join("accounts")).await else {
+        return true;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(account_key) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let Ok(content) = fs::read_to_string(&path).await else {
+            continue;
+        };
+        if let Ok(encrypted) = serde_json::from_str::<EncryptedBlob>(&content) {
+            return crypto::decrypt(key, &encrypted, account_key).is_ok();
+        }
+    }
+
+    true
+}
+
+/// Keyring entry for the store at `storage_dir`, so stores don't share a key
+#[cfg(feature = "keyring-support")]
+fn keyring_user(storage_dir: &Path) -> String {
+    let path = std::fs::canonicalize(storage_dir).unwrap_or_else(|_| storage_dir.to_path_buf());
+    let digest = Sha256::digest(path.to_string_lossy().as_bytes());
+    let hash: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}:{}", LEGACY_KEYRING_USER, hash)
+}
+
+/// Hash identifying a key without revealing it
+fn key_check(key: &EncryptionKey) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"rc-auth key check\0")
+        .chain_update(key.as_bytes())
+        .finalize();
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// Generate a random salt for [`derive_key`]
+pub(crate) fn generate_salt() -> Result<Vec<u8>> {
+    let mut salt = vec![0u8; SALT_LEN];
+    getrandom::fill(&mut salt)
+        .map_err(|e| RcAuthError::Crypto(format!("Failed to generate salt: {}", e)))?;
+    Ok(salt)
+}
+
+/// Derive an encryption key from a passphrase using Argon2id
+pub(crate) fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    kdf_params: &KdfParams,
+) -> Result<EncryptionKey> {
+    let params = Params::new(
+        kdf_params.memory_kib,
+        kdf_params.iterations,
+        kdf_params.parallelism,
+        Some(32),
+    )
+    .map_err(|e| RcAuthError::Crypto(format!("Invalid Argon2 params: {}", e)))?;
+    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+    let salt_string = SaltString::encode_b64(salt)
+        .map_err(|e| RcAuthError::Crypto(format!("Invalid salt: {}", e)))?;
+
+    let hash = argon2
+        .hash_password(passphrase.as_bytes(), &salt_string)
+        .map_err(|e| RcAuthError::Crypto(format!("Key derivation failed: {}", e)))?;
+
+    let key_bytes = hash
+        .hash
+        .ok_or_else(|| RcAuthError::Crypto("Argon2 hash returned no output".to_string()))?;
+
+    if key_bytes.len() != 32 {
+        return Err(RcAuthError::Crypto(format!(
+            "Expected 32 bytes, got {}",
+            key_bytes.len()
+        )));
+    }
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(key_bytes.as_bytes());
+
+    Ok(EncryptionKey::from_bytes(key))
 }
 
 impl std::fmt::Debug for KeyManager {
@@ -282,3 +774,108 @@ impl std::fmt::Debug for KeyManager {
             .finish()
     }
 }
+
+/// How a failure simulated by a test plays out
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    /// Panic, as if the process died
+    Crash,
+    /// Return an error to the caller
+    Error,
+}
+
+#[cfg(test)]
+impl Fault {
+    pub(crate) fn trigger(self, at: impl std::fmt::Debug) -> Result<()> {
+        match self {
+            Fault::Crash => panic!("simulated crash at {:?}", at),
+            Fault::Error => Err(RcAuthError::StorageIo(std::io::Error::other(format!(
+                "simulated failure at {:?}",
+                at
+            )))),
+        }
+    }
+}
+
+/// Persistent in-memory keyring for tests
+///
+/// Without platform features `keyring` falls back to a mock that forgets a
+/// key as soon as its entry is dropped. Tests that need keys to survive a
+/// reopen call [`test_keyring::enable`], which keeps them for the rest of the
+/// test's thread.
+#[cfg(all(test, feature = "keyring-support"))]
+pub(crate) mod test_keyring {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use keyring::credential::CredentialApi;
+
+    thread_local! {
+        static ENTRIES: RefCell<Option<HashMap<String, Vec<u8>>>> = const { RefCell::new(None) };
+    }
+
+    pub(crate) fn enable() {
+        ENTRIES.with(|entries| *entries.borrow_mut() = Some(HashMap::new()));
+    }
+
+    /// Move the key of the store at `storage_dir` to the shared entry older versions used
+    pub(crate) fn move_to_legacy_entry(storage_dir: &std::path::Path) {
+        let user = super::keyring_user(storage_dir);
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            let entries = entries.as_mut().unwrap();
+            let key = entries.remove(&user).unwrap();
+            entries.insert(super::LEGACY_KEYRING_USER.to_string(), key);
+        });
+    }
+
+    /// Whether the store at `storage_dir` has its own entry
+    pub(crate) fn has_store_entry(storage_dir: &std::path::Path) -> bool {
+        let user = super::keyring_user(storage_dir);
+        ENTRIES.with(|entries| entries.borrow().as_ref().unwrap().contains_key(&user))
+    }
+
+    pub(super) fn entry(user: &str) -> Option<keyring::Entry> {
+        let enabled = ENTRIES.with(|entries| entries.borrow().is_some());
+        enabled.then(|| {
+            keyring::Entry::new_with_credential(Box::new(TestCredential {
+                user: user.to_string(),
+            }))
+        })
+    }
+
+    #[derive(Debug)]
+    struct TestCredential {
+        user: String,
+    }
+
+    impl CredentialApi for TestCredential {
+        fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+            ENTRIES.with(|entries| {
+                entries
+                    .borrow_mut()
+                    .get_or_insert_with(HashMap::new)
+                    .insert(self.user.clone(), secret.to_vec());
+            });
+            Ok(())
+        }
+
+        fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+            ENTRIES
+                .with(|entries| entries.borrow().as_ref()?.get(&self.user).cloned())
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn delete_credential(&self) -> keyring::Result<()> {
+            ENTRIES
+                .with(|entries| entries.borrow_mut().as_mut()?.remove(&self.user))
+                .map(|_| ())
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+}
@@ -24,16 +24,17 @@
 //!     let config = RcAuthConfig::official_desktop();
 //!     let client = RcAuthClient::new(config)?;
 //!     
-//!     // Build authorization URL for user to visit
-//!     let auth_url = client.build_authorize_url(None)?;
-//!     println!("Visit: {}", auth_url);
+//!     // Build authorization URL for user to visit (with PKCE and a random state)
+//!     let attempt = client.build_authorize_url(None)?;
+//!     println!("Visit: {}", attempt.url());
 //!     
 //!     // After user authorizes and you receive the redirect URL with code...
+//!     // (custom apps can capture it with a `LoopbackReceiver`, see below)
 //!     let redirect_url = "http://localhost:8000/?code=..."; // From user
-//!     let code = client.parse_redirect(redirect_url, None)?;
+//!     let code = client.parse_redirect(redirect_url, Some(attempt.state()))?;
 //!     
 //!     // Complete the login flow
-//!     let session = client.complete_login_with_code(&code).await?;
+//!     let session = client.complete_login_with_code(&code, &attempt).await?;
 //!     println!("Logged in as: {}", session.profile.name);
 //!     
 //!     // Later, refresh the session when needed
@@ -45,6 +46,51 @@
 //! }
 //! ```
 //!
+//! ## Loopback Redirect (StandardCode)
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use rc_auth::{LoopbackReceiver, RcAuthClient, RcAuthConfig};
+//! use url::Url;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! // Listen on 127.0.0.1 and use the bound port as redirect URI
+//! let receiver = LoopbackReceiver::bind(&Url::parse("http://127.0.0.1/callback")?).await?;
+//! let config = RcAuthConfig::custom("your-client-id".to_string(), receiver.redirect_uri().clone());
+//! let client = RcAuthClient::new(config)?;
+//!
+//! let attempt = client.build_authorize_url(None)?;
+//! println!("Open in browser: {}", attempt.url());
+//!
+//! // Cancel from elsewhere (e.g. a UI button) with `receiver.cancel_handle()`
+//! let code = receiver
+//!     .wait_for_code(&client, Some(attempt.state()), Duration::from_secs(300))
+//!     .await?;
+//! let _session = client.complete_login_with_code(&code, &attempt).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Device Code Flow (headless / CLI)
+//!
+//! ```no_run
+//! use rc_auth::{RcAuthClient, RcAuthConfig};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let client = RcAuthClient::new(RcAuthConfig::official_desktop())?;
+//!
+//! // Ask Microsoft for a user code and show it to the user
+//! let device = client.request_device_code().await?;
+//! println!("Visit {} and enter {}", device.verification_uri, device.user_code);
+//!
+//! // Polls until the user finishes, then runs the XBL -> XSTS -> Minecraft chain
+//! let session = client.complete_login_with_device_code(&device).await?;
+//! println!("Logged in as: {}", session.profile.name);
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Token Storage
 //!
 //! The crate provides a `TokenStore` trait for persisting sessions:
@@ -67,6 +113,8 @@
 //! #     profile: McProfile { id: "uuid".to_string(), name: "Player".to_string(), skins: vec![], capes: vec![] },
 //! #     xuid: None,
 //! #     gamertag: None,
+//! #     entitlements: None,
+//! #     certificates: None,
 //! # };
 //! store.save(session.account_key(), &session).await?;
 //!
@@ -101,6 +149,8 @@
 //! #     profile: McProfile { id: "uuid".to_string(), name: "Player".to_string(), skins: vec![], capes: vec![] },
 //! #     xuid: None,
 //! #     gamertag: None,
+//! #     entitlements: None,
+//! #     certificates: None,
 //! # };
 //! store.save(session.account_key(), &session).await?;
 //!
@@ -110,6 +160,77 @@
 //! # }
 //! ```
 //!
+//! ## Offline Accounts
+//!
+//! Offline (non-premium) accounts live in the same stores as Microsoft sessions:
+//!
+//! ```
+//! use rc_auth::{Account, MemoryTokenStore, OfflineAccount, TokenStore, UserType};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let store = MemoryTokenStore::new();
+//!
+//! // UUID is derived from "OfflinePlayer:<name>" like the vanilla server does
+//! let account = Account::from(OfflineAccount::new("Steve")?);
+//! store.save_account(account.account_key(), &account).await?;
+//!
+//! for key in store.list_accounts().await {
+//!     if let Some(account) = store.load_account(&key).await {
+//!         assert_eq!(account.user_type(), UserType::Local);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # tokio_test::block_on(example());
+//! ```
+//!
+//! ## Third-Party Yggdrasil Servers
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use rc_auth::{Account, RcAuthConfig, TokenStore, YggdrasilClient};
+//!
+//! # async fn example(store: &dyn TokenStore) -> anyhow::Result<()> {
+//! // Accepts the server's home page; the API root is discovered from
+//! // the X-Authlib-Injector-API-Location header
+//! let client = YggdrasilClient::discover(RcAuthConfig::default(), "https://skins.example.com").await?;
+//! let session = client.authenticate("alex@example.com", "password").await?;
+//!
+//! // Pass these to the JVM when launching the game
+//! let _jvm_args = session.jvm_args(Path::new("authlib-injector.jar"));
+//!
+//! let account = Account::from(session);
+//! store.save_account(account.account_key(), &account).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Account Manager
+//!
+//! `AccountManager` tracks the active account and refreshes sessions on demand,
+//! so callers never have to check `needs_refresh()` themselves:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use rc_auth::{AccountManager, MemoryTokenStore, RcAuthClient, RcAuthConfig};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let client = RcAuthClient::new(RcAuthConfig::official_desktop())?;
+//! let manager = AccountManager::new(client, Arc::new(MemoryTokenStore::new()));
+//!
+//! // Refresh every session whose Minecraft token expires within the next hour
+//! let _refresher = manager.spawn_auto_refresh(Duration::from_secs(300), Duration::from_secs(3600));
+//!
+//! if let Some(account) = manager.active().await? {
+//!     println!("Launching as {}", account.name());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Important Notes
 //!
 //! - For development, use `RcAuthConfig::official_desktop()` with the official launcher's client ID
@@ -117,23 +238,43 @@
 //! - Tokens should be stored securely and never logged
 //! - The MC access token expires after 24 hours and needs refresh
 
+pub mod account;
+pub mod attributes;
+pub mod bundle;
+pub mod certificates;
 pub mod client;
 pub mod config;
 pub mod crypto;
 pub mod errors;
 pub mod file_store;
 pub mod key_manager;
+pub mod loopback;
+pub mod manager;
 pub mod models;
+pub mod pkce;
+pub mod profile;
+mod retry;
 pub mod secret;
 pub mod session;
 pub mod store;
+pub mod yggdrasil;
 
 // Re-export main types
+pub use account::{Account, OfflineAccount, UserType};
+pub use bundle::AccountBundle;
 pub use client::RcAuthClient;
-pub use config::{AuthorizeFlavor, RcAuthConfig};
-pub use errors::{RcAuthError, Result, XstsError};
-pub use file_store::FileTokenStore;
-pub use models::McProfile;
+pub use config::{AuthorizeFlavor, Endpoints, RcAuthConfig};
+pub use errors::{NameChangeError, OAuthError, OAuthErrorKind, RcAuthError, Result, XstsError};
+pub use file_store::{FileStoreOptions, FileTokenStore, ImportSummary};
+pub use key_manager::{KdfParams, KeyMode};
+pub use loopback::{LoopbackCancelHandle, LoopbackReceiver};
+pub use manager::{AccountEvent, AccountManager, AutoRefreshHandle};
+pub use models::{
+    DeviceCode, Entitlements, McProfile, NameChangeInfo, PlayerAttributes, PlayerAttributesUpdate,
+    ProfileDiff, SkinVariant,
+};
+pub use pkce::LoginAttempt;
 pub use secret::{NoSecretProvider, SecretProvider, StaticSecretProvider};
-pub use session::{McToken, MsTokens, Session, XblToken, XstsToken};
-pub use store::{MemoryTokenStore, TokenStore};
+pub use session::{McToken, MsTokens, PlayerCertificates, Session, XblToken, XstsToken};
+pub use store::{AccountUpdate, MemoryTokenStore, SessionUpdate, StoreEvent, TokenStore};
+pub use yggdrasil::{YggdrasilClient, YggdrasilSession};
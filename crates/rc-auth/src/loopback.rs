@@ -0,0 +1,303 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tracing::{debug, instrument, warn};
+use url::Url;
+
+use crate::client::RcAuthClient;
+use crate::errors::{RcAuthError, Result};
+
+/// Maximum time a single browser connection may take to send its request
+const CONNECTION_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of the request head we are willing to read
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+const SUCCESS_PAGE: &str = "<!DOCTYPE html><html><head><title>rauncher-mc</title></head>\
+<body><h1>Login complete</h1><p>You can close this window and return to the launcher.</p></body></html>";
+
+const FAILURE_PAGE: &str = "<!DOCTYPE html><html><head><title>rauncher-mc</title></head>\
+<body><h1>Login failed</h1><p>Return to the launcher and try again.</p></body></html>";
+
+/// Local HTTP listener that captures the OAuth redirect for `AuthorizeFlavor::StandardCode`
+///
+/// Binds to `127.0.0.1` on the port of the configured redirect URI (or an ephemeral
+/// port if none is given) and waits for the browser to be redirected back to it.
+#[derive(Debug)]
+pub struct LoopbackReceiver {
+    listener: TcpListener,
+    redirect_uri: Url,
+    cancel: Arc<Notify>,
+}
+
+/// Handle used to cancel a pending [`LoopbackReceiver::wait_for_code`]
+#[derive(Debug, Clone)]
+pub struct LoopbackCancelHandle {
+    cancel: Arc<Notify>,
+}
+
+impl LoopbackCancelHandle {
+    /// Stop waiting for the redirect; the receiver returns `RcAuthError::UserCancelled`
+    pub fn cancel(&self) {
+        self.cancel.notify_one();
+    }
+}
+
+impl LoopbackReceiver {
+    /// Bind a listener for the given loopback redirect URI
+    ///
+    /// The URI must use `http` and point at `127.0.0.1` or `localhost`. A missing
+    /// port is replaced with the ephemeral port chosen by the OS; use
+    /// [`LoopbackReceiver::redirect_uri`] to get the final URI.
+    #[instrument]
+    pub async fn bind(redirect_uri: &Url) -> Result<Self> {
+        let is_loopback = matches!(redirect_uri.host_str(), Some("127.0.0.1" | "localhost"));
+        if redirect_uri.scheme() != "http" || !is_loopback {
+            return Err(RcAuthError::InvalidRedirect);
+        }
+
+        let port = redirect_uri.port().unwrap_or(0);
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).await?;
+        let local_port = listener.local_addr()?.port();
+
+        let mut redirect_uri = redirect_uri.clone();
+        redirect_uri
+            .set_port(Some(local_port))
+            .map_err(|_| RcAuthError::InvalidRedirect)?;
+
+        debug!("Loopback receiver listening on {}", redirect_uri);
+        Ok(Self {
+            listener,
+            redirect_uri,
+            cancel: Arc::new(Notify::new()),
+        })
+    }
+
+    /// Redirect URI the browser must be sent back to
+    pub fn redirect_uri(&self) -> &Url {
+        &self.redirect_uri
+    }
+
+    /// Get a handle that can cancel the wait from another task
+    pub fn cancel_handle(&self) -> LoopbackCancelHandle {
+        LoopbackCancelHandle {
+            cancel: self.cancel.clone(),
+        }
+    }
+
+    /// Wait for the OAuth redirect and return the authorization code
+    ///
+    /// The redirect is validated with [`RcAuthClient::parse_redirect`], so a
+    /// mismatching `state` yields `RcAuthError::StateMismatch`. Requests for other
+    /// paths (e.g. `/favicon.ico`) are answered with 404 and ignored.
+    #[instrument(skip(self, client, expected_state))]
+    pub async fn wait_for_code(
+        self,
+        client: &RcAuthClient,
+        expected_state: Option<&str>,
+        timeout: Duration,
+    ) -> Result<String> {
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+
+        loop {
+            let stream = tokio::select! {
+                _ = &mut deadline => return Err(RcAuthError::RedirectTimeout),
+                _ = self.cancel.notified() => return Err(RcAuthError::UserCancelled),
+                accepted = self.listener.accept() => accepted?.0,
+            };
+
+            match tokio::time::timeout(
+                CONNECTION_READ_TIMEOUT,
+                self.handle_connection(stream, client, expected_state),
+            )
+            .await
+            {
+                Ok(Ok(Some(result))) => return result,
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => warn!("Failed to handle loopback connection: {}", e),
+                Err(_) => warn!("Loopback connection timed out"),
+            }
+        }
+    }
+
+    /// Handle one browser connection
+    ///
+    /// Returns `None` when the request was not the OAuth redirect.
+    async fn handle_connection(
+        &self,
+        mut stream: TcpStream,
+        client: &RcAuthClient,
+        expected_state: Option<&str>,
+    ) -> Result<Option<Result<String>>> {
+        let target = match read_request_target(&mut stream).await? {
+            Some(target) => target,
+            None => {
+                write_response(&mut stream, "400 Bad Request", "").await?;
+                return Ok(None);
+            }
+        };
+
+        let url = self.redirect_uri.join(&target)?;
+        if url.path() != self.redirect_uri.path() {
+            debug!("Ignoring loopback request for {}", url.path());
+            write_response(&mut stream, "404 Not Found", "").await?;
+            return Ok(None);
+        }
+
+        let result = client.parse_redirect(url.as_str(), expected_state);
+        match &result {
+            Ok(_) => write_response(&mut stream, "200 OK", SUCCESS_PAGE).await?,
+            Err(_) => write_response(&mut stream, "400 Bad Request", FAILURE_PAGE).await?,
+        }
+
+        Ok(Some(result))
+    }
+}
+
+/// Read the request head and return the target of a `GET` request
+async fn read_request_target(stream: &mut TcpStream) -> Result<Option<String>> {
+    let mut buffer = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 || buffer.len() + read > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&buffer);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) if target.starts_with('/') => Ok(Some(target.to_string())),
+        _ => Ok(None),
+    }
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RcAuthConfig;
+
+    async fn bind_receiver() -> (LoopbackReceiver, RcAuthClient) {
+        let redirect_uri = Url::parse("http://127.0.0.1/callback").unwrap();
+        let receiver = LoopbackReceiver::bind(&redirect_uri).await.unwrap();
+        let config =
+            RcAuthConfig::custom("test-client".to_string(), receiver.redirect_uri().clone());
+        let client = RcAuthClient::new(config).unwrap();
+        (receiver, client)
+    }
+
+    #[tokio::test]
+    async fn test_receives_code() {
+        let (receiver, client) = bind_receiver().await;
+        let mut url = receiver.redirect_uri().clone();
+        url.set_query(Some("code=abc123&state=xyz"));
+
+        let browser = tokio::spawn(async move { reqwest::get(url).await.unwrap().status() });
+
+        let code = receiver
+            .wait_for_code(&client, Some("xyz"), Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(code, "abc123");
+        assert_eq!(browser.await.unwrap(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_state_mismatch() {
+        let (receiver, client) = bind_receiver().await;
+        let mut url = receiver.redirect_uri().clone();
+        url.set_query(Some("code=abc123&state=evil"));
+
+        let browser = tokio::spawn(async move { reqwest::get(url).await.unwrap().status() });
+
+        let result = receiver
+            .wait_for_code(&client, Some("xyz"), Duration::from_secs(5))
+            .await;
+
+        assert!(matches!(result, Err(RcAuthError::StateMismatch)));
+        assert_eq!(browser.await.unwrap(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ignores_other_paths() {
+        let (receiver, client) = bind_receiver().await;
+        let favicon = receiver.redirect_uri().join("/favicon.ico").unwrap();
+        let mut url = receiver.redirect_uri().clone();
+        url.set_query(Some("code=abc123"));
+
+        let browser = tokio::spawn(async move {
+            let favicon_status = reqwest::get(favicon).await.unwrap().status();
+            let status = reqwest::get(url).await.unwrap().status();
+            (favicon_status, status)
+        });
+
+        let code = receiver
+            .wait_for_code(&client, None, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(code, "abc123");
+        assert_eq!(
+            browser.await.unwrap(),
+            (reqwest::StatusCode::NOT_FOUND, reqwest::StatusCode::OK)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (receiver, client) = bind_receiver().await;
+
+        let result = receiver
+            .wait_for_code(&client, None, Duration::from_millis(50))
+            .await;
+
+        assert!(matches!(result, Err(RcAuthError::RedirectTimeout)));
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let (receiver, client) = bind_receiver().await;
+        let cancel = receiver.cancel_handle();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cancel.cancel();
+        });
+
+        let result = receiver
+            .wait_for_code(&client, None, Duration::from_secs(5))
+            .await;
+
+        assert!(matches!(result, Err(RcAuthError::UserCancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_non_loopback_uri() {
+        let redirect_uri = Url::parse("https://example.com/callback").unwrap();
+        let result = LoopbackReceiver::bind(&redirect_uri).await;
+
+        assert!(matches!(result, Err(RcAuthError::InvalidRedirect)));
+    }
+}
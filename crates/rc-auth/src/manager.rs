@@ -0,0 +1,662 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, instrument, warn};
+
+use crate::account::Account;
+use crate::client::RcAuthClient;
+use crate::errors::{RcAuthError, Result};
+use crate::models::ProfileDiff;
+use crate::session::Session;
+use crate::store::TokenStore;
+
+/// Number of events buffered for slow subscribers before they start lagging
+const EVENT_CAPACITY: usize = 32;
+
+/// Shortest interval between background refresh passes
+const MIN_AUTO_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Event emitted by [`AccountManager`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountEvent {
+    /// A refresh picked up profile changes (requires `RcAuthConfig::refresh_profile`)
+    ProfileChanged {
+        account_key: String,
+        diff: ProfileDiff,
+    },
+    /// A background refresh was rejected and the user has to sign in again
+    ///
+    /// The account isn't refreshed in the background again until a new
+    /// session is saved for it.
+    ReloginRequired { account_key: String },
+}
+
+/// Keeps track of the active account and hands out valid sessions
+///
+/// Sessions are refreshed through [`RcAuthClient::refresh_session`] when they
+/// need it and saved back to the store. Concurrent callers asking for the same
+/// account share a single refresh, so a refresh token is never spent twice.
+#[derive(Clone)]
+pub struct AccountManager {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: RcAuthClient,
+    store: Arc<dyn TokenStore>,
+    active: RwLock<Option<String>>,
+    refresh_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Refresh token each account needing a re-login was rejected with
+    relogin_required: Mutex<HashMap<String, Option<String>>>,
+    events: broadcast::Sender<AccountEvent>,
+}
+
+impl AccountManager {
+    pub fn new(client: RcAuthClient, store: Arc<dyn TokenStore>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                client,
+                store,
+                active: RwLock::new(None),
+                refresh_locks: Mutex::new(HashMap::new()),
+                relogin_required: Mutex::new(HashMap::new()),
+                events: broadcast::channel(EVENT_CAPACITY).0,
+            }),
+        }
+    }
+
+    pub fn client(&self) -> &RcAuthClient {
+        &self.inner.client
+    }
+
+    pub fn store(&self) -> &Arc<dyn TokenStore> {
+        &self.inner.store
+    }
+
+    /// Subscribe to account events
+    pub fn subscribe(&self) -> broadcast::Receiver<AccountEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// Account key of the active account
+    pub fn active_account(&self) -> Option<String> {
+        self.inner.active.read().ok()?.clone()
+    }
+
+    /// Make a stored account the active one
+    pub async fn set_active_account(&self, account_key: &str) -> Result<()> {
+        if self.inner.store.load_account(account_key).await.is_none() {
+            return Err(RcAuthError::AccountNotFound(account_key.to_string()));
+        }
+
+        self.set_active(Some(account_key.to_string()));
+        Ok(())
+    }
+
+    pub fn clear_active_account(&self) {
+        self.set_active(None);
+    }
+
+    /// Store an account, making it active if no account is active yet
+    pub async fn add_account(&self, account: impl Into<Account>) -> Result<()> {
+        let account = account.into();
+        self.inner
+            .store
+            .save_account(account.account_key(), &account)
+            .await?;
+
+        if let Ok(mut active) = self.inner.active.write()
+            && active.is_none()
+        {
+            *active = Some(account.account_key().to_string());
+        }
+        Ok(())
+    }
+
+    /// Remove an account, clearing it as active if needed
+    pub async fn remove_account(&self, account_key: &str) -> Result<()> {
+        self.inner.store.remove(account_key).await?;
+        self.prune_refresh_lock(account_key);
+        self.relogin_required().remove(account_key);
+
+        if let Ok(mut active) = self.inner.active.write()
+            && active.as_deref() == Some(account_key)
+        {
+            *active = None;
+        }
+        Ok(())
+    }
+
+    /// Load an account, refreshing its session first if it needs it
+    #[instrument(skip(self))]
+    pub async fn account(&self, account_key: &str) -> Result<Account> {
+        self.refresh_within(account_key, chrono::Duration::zero())
+            .await
+    }
+
+    /// Get a valid Microsoft session, refreshing it first if it needs it
+    pub async fn session(&self, account_key: &str) -> Result<Session> {
+        self.account(account_key)
+            .await?
+            .into_session()
+            .ok_or_else(|| RcAuthError::AccountNotFound(account_key.to_string()))
+    }
+
+    /// Load the active account, refreshing its session first if it needs it
+    ///
+    /// Returns `Ok(None)` when no account is active.
+    pub async fn active(&self) -> Result<Option<Account>> {
+        match self.active_account() {
+            Some(account_key) => Ok(Some(self.account(&account_key).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Start a background task refreshing sessions ahead of expiry
+    ///
+    /// Every `interval`, each stored Microsoft session whose Minecraft token
+    /// expires within `lead` is refreshed. `interval` is raised to at least one
+    /// second. The task stops when the returned handle is dropped.
+    pub fn spawn_auto_refresh(&self, interval: Duration, lead: Duration) -> AutoRefreshHandle {
+        let manager = self.clone();
+        let interval = interval.max(MIN_AUTO_REFRESH_INTERVAL);
+        let lead = chrono::Duration::from_std(lead).unwrap_or(chrono::Duration::zero());
+
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                manager.refresh_all(lead).await;
+            }
+        });
+
+        AutoRefreshHandle { task }
+    }
+
+    async fn refresh_all(&self, lead: chrono::Duration) {
+        for account_key in self.inner.store.list_accounts().await {
+            let Ok(account) = self.load(&account_key).await else {
+                continue;
+            };
+
+            // Don't spend a rejected refresh token again until a new one is saved
+            let refresh_token = account
+                .as_session()
+                .and_then(|session| session.ms.refresh_token.clone());
+            if self.relogin_required().get(&account_key) == Some(&refresh_token) {
+                continue;
+            }
+
+            match self.refresh_within(&account_key, lead).await {
+                Ok(_) => {
+                    self.relogin_required().remove(&account_key);
+                }
+                Err(e) if e.requires_relogin() => {
+                    warn!("{} has to sign in again: {}", account_key, e);
+                    self.relogin_required()
+                        .insert(account_key.clone(), refresh_token);
+                    let _ = self
+                        .inner
+                        .events
+                        .send(AccountEvent::ReloginRequired { account_key });
+                }
+                Err(e) => warn!("Background refresh of {} failed: {}", account_key, e),
+            }
+        }
+    }
+
+    fn relogin_required(&self) -> std::sync::MutexGuard<'_, HashMap<String, Option<String>>> {
+        self.inner
+            .relogin_required
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Refresh an account if its session expires within `lead`
+    async fn refresh_within(&self, account_key: &str, lead: chrono::Duration) -> Result<Account> {
+        let account = self.load(account_key).await?;
+        if !expires_within(&account, lead) {
+            return Ok(account);
+        }
+
+        let lock = self.refresh_lock(account_key);
+        let result = {
+            let _guard = lock.lock().await;
+            self.refresh_locked(account_key, lead).await
+        };
+        drop(lock);
+        self.prune_refresh_lock(account_key);
+        result
+    }
+
+    /// Refresh an account while holding its refresh lock
+    async fn refresh_locked(&self, account_key: &str, lead: chrono::Duration) -> Result<Account> {
+        // Another caller may have refreshed while we were waiting
+        let account = self.load(account_key).await?;
+        let session = match &account {
+            Account::Microsoft(session) if expires_within(&account, lead) => session,
+            _ => return Ok(account),
+        };
+
+        debug!("Refreshing session for {}", account_key);
+        let mut refreshed = (**session).clone();
+        let result = self
+            .inner
+            .client
+            .refresh_session_in_place(&mut refreshed)
+            .await;
+
+        // Save even on failure so tokens obtained before it (such as a rotated
+        // refresh token) aren't lost. Only what the refresh owns is written, so
+        // changes saved meanwhile (e.g. certificates or a cape) are kept.
+        let refreshed = if refreshed != **session {
+            let refresh_profile = self.inner.client.config.refresh_profile;
+            let update = refreshed.clone();
+            self.inner
+                .store
+                .update_session(
+                    account_key,
+                    Box::new(move |stored| apply_refresh(stored, update, refresh_profile)),
+                )
+                .await?
+                .unwrap_or(refreshed)
+        } else {
+            refreshed
+        };
+        result?;
+
+        if let Some(diff) = ProfileDiff::between(&session.profile, &refreshed.profile) {
+            debug!("Profile of {} changed", account_key);
+            // Nobody listening is fine
+            let _ = self.inner.events.send(AccountEvent::ProfileChanged {
+                account_key: account_key.to_string(),
+                diff,
+            });
+        }
+
+        Ok(refreshed.into())
+    }
+
+    async fn load(&self, account_key: &str) -> Result<Account> {
+        self.inner
+            .store
+            .load_account(account_key)
+            .await
+            .ok_or_else(|| RcAuthError::AccountNotFound(account_key.to_string()))
+    }
+
+    fn refresh_lock(&self, account_key: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self
+            .inner
+            .refresh_locks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        locks.entry(account_key.to_string()).or_default().clone()
+    }
+
+    /// Forget the refresh lock of an account once no caller holds it
+    fn prune_refresh_lock(&self, account_key: &str) {
+        let mut locks = self
+            .inner
+            .refresh_locks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if locks
+            .get(account_key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(account_key);
+        }
+    }
+
+    fn set_active(&self, account_key: Option<String>) {
+        if let Ok(mut active) = self.inner.active.write() {
+            *active = account_key;
+        }
+    }
+}
+
+impl std::fmt::Debug for AccountManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountManager")
+            .field("active", &self.active_account())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle to the background refresh task; stops it when dropped
+#[derive(Debug)]
+pub struct AutoRefreshHandle {
+    task: JoinHandle<()>,
+}
+
+impl AutoRefreshHandle {
+    /// Stop the background task
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for AutoRefreshHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Copy what a session refresh obtains into the stored session
+fn apply_refresh(stored: &mut Session, refreshed: Session, refresh_profile: bool) {
+    stored.ms = refreshed.ms;
+    stored.xbl = refreshed.xbl;
+    stored.xsts = refreshed.xsts;
+    stored.mc = refreshed.mc;
+    stored.entitlements = refreshed.entitlements;
+    if refresh_profile {
+        stored.profile = refreshed.profile;
+        stored.xuid = refreshed.xuid;
+        stored.gamertag = refreshed.gamertag;
+    }
+}
+
+fn expires_within(account: &Account, lead: chrono::Duration) -> bool {
+    match account {
+        Account::Microsoft(session) => {
+            session.needs_refresh() || Utc::now() + lead >= session.mc.expires_at
+        }
+        Account::Yggdrasil(_) | Account::Offline(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::OfflineAccount;
+    use crate::client::tests::{
+        expired_session, mock_client, mount_xbox_chain, ms_token_body, test_session,
+    };
+    use crate::session::McToken;
+    use crate::store::MemoryTokenStore;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn mount_refresh(server: &MockServer, expected: u64) {
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(ms_token_body("new-access", "new-refresh"))
+                    .set_delay(Duration::from_millis(50)),
+            )
+            .expect(expected)
+            .mount(server)
+            .await;
+        mount_xbox_chain(server).await;
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_callers_share_one_refresh() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 1).await;
+
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+        let session = expired_session();
+        manager.add_account(session.clone()).await.unwrap();
+        assert_eq!(
+            manager.active_account().as_deref(),
+            Some(session.account_key())
+        );
+
+        let (a, b, c) = tokio::join!(
+            manager.session(session.account_key()),
+            manager.session(session.account_key()),
+            manager.session(session.account_key()),
+        );
+
+        for refreshed in [a, b, c] {
+            let refreshed = refreshed.unwrap();
+            assert_eq!(refreshed.mc.access_token, "mc-token");
+            assert_eq!(refreshed.ms.refresh_token.as_deref(), Some("new-refresh"));
+        }
+        assert!(manager.inner.refresh_locks.lock().unwrap().is_empty());
+
+        let stored = manager.store().load(session.account_key()).await.unwrap();
+        assert_eq!(stored.mc.access_token, "mc-token");
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_keeps_rotated_tokens() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/xsts/authorize"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        mount_refresh(&server, 1).await;
+
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+        let session = expired_session();
+        manager.add_account(session.clone()).await.unwrap();
+
+        assert!(manager.session(session.account_key()).await.is_err());
+
+        let stored = manager.store().load(session.account_key()).await.unwrap();
+        assert_eq!(stored.ms.refresh_token.as_deref(), Some("new-refresh"));
+        assert_eq!(stored.xbl.token, "xbl-token");
+        assert!(stored.needs_refresh());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_concurrent_updates() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 1).await;
+
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+        let session = expired_session();
+        let key = session.account_key().to_string();
+        manager.add_account(session.clone()).await.unwrap();
+
+        // Lands while the token request is in flight
+        let (refreshed, updated) = tokio::join!(manager.session(&key), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            manager
+                .store()
+                .update_session(
+                    &key,
+                    Box::new(|stored| stored.profile.name = "Renamed".to_string()),
+                )
+                .await
+        });
+        updated.unwrap().unwrap();
+
+        let refreshed = refreshed.unwrap();
+        assert_eq!(refreshed.profile.name, "Renamed");
+        assert_eq!(refreshed.ms.refresh_token.as_deref(), Some("new-refresh"));
+
+        let stored = manager.store().load(&key).await.unwrap();
+        assert_eq!(stored, refreshed);
+    }
+
+    #[tokio::test]
+    async fn test_profile_change_event() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 1).await;
+
+        let mut client = mock_client(&server);
+        client.config.refresh_profile = true;
+        let manager = AccountManager::new(client, Arc::new(MemoryTokenStore::new()));
+        let mut events = manager.subscribe();
+
+        // The served profile is "Notch" with one active skin and no capes
+        let mut session = expired_session();
+        session.profile.name = "OldName".to_string();
+        session.gamertag = None;
+        manager.add_account(session.clone()).await.unwrap();
+
+        let refreshed = manager.session(session.account_key()).await.unwrap();
+        assert_eq!(refreshed.profile.name, "Notch");
+        assert_eq!(refreshed.gamertag.as_deref(), Some("Gamer"));
+
+        // Still stored under the same key after the rename
+        assert_eq!(
+            manager.store().list_accounts().await,
+            vec![session.account_key()]
+        );
+        assert_eq!(
+            manager.active_account().as_deref(),
+            Some(session.account_key())
+        );
+
+        let AccountEvent::ProfileChanged { account_key, diff } = events.try_recv().unwrap() else {
+            panic!("expected a profile change");
+        };
+        assert_eq!(account_key, session.account_key());
+        assert_eq!(diff.old_name.as_deref(), Some("OldName"));
+        assert_eq!(diff.new_name, "Notch");
+        assert!(diff.skin_changed);
+        assert!(diff.capes_gained.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_background_refresh_stops_until_relogin() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("refresh_token=old-refresh"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "invalid_grant",
+                "error_description": "The refresh token has expired."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth20_token.srf"))
+            .and(body_string_contains("refresh_token=relogin-refresh"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(ms_token_body("new-access", "new-refresh")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_xbox_chain(&server).await;
+
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+        let mut events = manager.subscribe();
+        let mut session = expired_session();
+        let key = session.account_key().to_string();
+        manager.add_account(session.clone()).await.unwrap();
+
+        manager.refresh_all(chrono::Duration::zero()).await;
+        manager.refresh_all(chrono::Duration::zero()).await;
+        assert_eq!(
+            events.try_recv().unwrap(),
+            AccountEvent::ReloginRequired {
+                account_key: key.clone()
+            }
+        );
+        assert!(events.try_recv().is_err());
+
+        // Signing in again saves a new refresh token
+        session.ms.refresh_token = Some("relogin-refresh".to_string());
+        manager.add_account(session).await.unwrap();
+        manager.refresh_all(chrono::Duration::zero()).await;
+
+        let stored = manager.store().load(&key).await.unwrap();
+        assert_eq!(stored.ms.refresh_token.as_deref(), Some("new-refresh"));
+        assert!(!stored.needs_refresh());
+    }
+
+    #[tokio::test]
+    async fn test_valid_session_is_not_refreshed() {
+        let server = MockServer::start().await;
+        mount_refresh(&server, 0).await;
+
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+        let session = test_session();
+        manager.add_account(session.clone()).await.unwrap();
+
+        let active = manager.active().await.unwrap().unwrap();
+        assert_eq!(active.as_session(), Some(&session));
+    }
+
+    #[tokio::test]
+    async fn test_active_account() {
+        let server = MockServer::start().await;
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+
+        assert!(matches!(
+            manager.set_active_account("missing").await,
+            Err(RcAuthError::AccountNotFound(_))
+        ));
+
+        let offline = OfflineAccount::new("Steve").unwrap();
+        manager.add_account(test_session()).await.unwrap();
+        manager.add_account(offline.clone()).await.unwrap();
+        assert_eq!(
+            manager.active_account().as_deref(),
+            Some(test_session().account_key())
+        );
+
+        manager
+            .set_active_account(offline.account_key())
+            .await
+            .unwrap();
+        let active = manager.active().await.unwrap().unwrap();
+        assert_eq!(active.name(), "Steve");
+        assert!(matches!(
+            manager.session(offline.account_key()).await,
+            Err(RcAuthError::AccountNotFound(_))
+        ));
+
+        manager.remove_account(offline.account_key()).await.unwrap();
+        assert!(manager.active_account().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_background_refresh_ahead_of_expiry() {
+        let server = MockServer::start().await;
+        // XSTS is still valid, so only the Minecraft login is redone
+        mount_refresh(&server, 0).await;
+
+        let manager = AccountManager::new(mock_client(&server), Arc::new(MemoryTokenStore::new()));
+        let mut session = test_session();
+        // Still valid, but inside the refresh lead
+        session.mc = McToken::new("old-mc".to_string(), 600);
+        manager.add_account(session.clone()).await.unwrap();
+
+        let handle =
+            manager.spawn_auto_refresh(Duration::from_millis(10), Duration::from_secs(3600));
+
+        let refreshed = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let stored = manager.store().load(session.account_key()).await.unwrap();
+                if stored.mc.access_token == "mc-token" {
+                    return stored;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        handle.stop();
+
+        assert!(!refreshed.needs_refresh());
+    }
+
+    #[tokio::test]
+    async fn test_auto_refresh_with_zero_interval() {
+        let manager = AccountManager::new(
+            RcAuthClient::new(Default::default()).unwrap(),
+            Arc::new(MemoryTokenStore::new()),
+        );
+
+        let handle = manager.spawn_auto_refresh(Duration::ZERO, Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!handle.task.is_finished());
+    }
+}
@@ -1,5 +1,10 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::errors::{OAuthError, OAuthErrorKind};
+
 /// Microsoft OAuth token response (from both code and refresh_token grants)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MsTokenResponse {
@@ -11,6 +16,80 @@ pub struct MsTokenResponse {
     pub scope: Option<String>,
 }
 
+/// Microsoft OAuth error response (token endpoint)
+#[derive(Debug, Clone, Deserialize)]
+pub struct MsOAuthErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub error_description: Option<String>,
+    #[serde(default)]
+    pub error_codes: Vec<u64>,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+}
+
+impl From<MsOAuthErrorResponse> for OAuthError {
+    fn from(response: MsOAuthErrorResponse) -> Self {
+        Self {
+            kind: OAuthErrorKind::from_code(&response.error),
+            description: response.error_description,
+            error_codes: response.error_codes,
+            correlation_id: response.correlation_id,
+        }
+    }
+}
+
+/// Microsoft device code response
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceCodeResponse {
+    pub user_code: String,
+    pub device_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    #[serde(default = "default_device_code_interval")]
+    pub interval: u64,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+fn default_device_code_interval() -> u64 {
+    5
+}
+
+/// Pending device code authorization shown to the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceCode {
+    /// Code the user has to enter on the verification page
+    pub user_code: String,
+    /// Page the user has to visit
+    pub verification_uri: String,
+    /// Optional human-readable instructions from Microsoft
+    pub message: Option<String>,
+    /// Minimum delay between token polls
+    pub interval: Duration,
+    pub expires_at: DateTime<Utc>,
+    pub(crate) device_code: String,
+}
+
+impl DeviceCode {
+    pub fn is_expired(&self) -> bool {
+        Utc::now() >= self.expires_at
+    }
+}
+
+impl From<DeviceCodeResponse> for DeviceCode {
+    fn from(response: DeviceCodeResponse) -> Self {
+        Self {
+            user_code: response.user_code,
+            verification_uri: response.verification_uri,
+            message: response.message,
+            interval: Duration::from_secs(response.interval),
+            expires_at: Utc::now() + chrono::Duration::seconds(response.expires_in as i64),
+            device_code: response.device_code,
+        }
+    }
+}
+
 /// Xbox Live user.authenticate request
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "PascalCase")]
@@ -28,6 +107,24 @@ pub struct XblAuthProperties {
     pub rps_ticket: String,
 }
 
+/// Deserialize an optional timestamp, treating unparseable values as missing
+///
+/// Xbox Live sends 7 fractional digits (`2099-01-01T00:00:00.0000000Z`), and
+/// sessions saved by older versions may hold arbitrary strings.
+pub(crate) fn deserialize_timestamp<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    Ok(value.and_then(|v| {
+        DateTime::parse_from_rfc3339(&v)
+            .ok()
+            .map(|d| d.with_timezone(&Utc))
+    }))
+}
+
 /// Xbox Live user.authenticate response
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -36,8 +133,8 @@ pub struct XblAuthResponse {
     pub display_claims: XblDisplayClaims,
     #[serde(default)]
     pub issue_instant: Option<String>,
-    #[serde(default)]
-    pub not_after: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub not_after: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -80,8 +177,8 @@ pub struct XstsAuthResponse {
     pub display_claims: XblDisplayClaims,
     #[serde(default)]
     pub issue_instant: Option<String>,
-    #[serde(default)]
-    pub not_after: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub not_after: Option<DateTime<Utc>>,
 }
 
 /// XSTS error response
@@ -123,6 +220,55 @@ pub struct McProfile {
     pub capes: Vec<McCape>,
 }
 
+impl McProfile {
+    /// Currently worn skin
+    pub fn active_skin(&self) -> Option<&McSkin> {
+        self.skins.iter().find(|s| s.state == "ACTIVE")
+    }
+
+    /// Currently shown cape, if any
+    pub fn active_cape(&self) -> Option<&McCape> {
+        self.capes.iter().find(|c| c.state == "ACTIVE")
+    }
+}
+
+/// Changes between two versions of a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileDiff {
+    /// Previous name, if the name changed
+    pub old_name: Option<String>,
+    pub new_name: String,
+    /// Whether the active skin is different
+    pub skin_changed: bool,
+    /// Capes present in the new profile but not in the old one
+    pub capes_gained: Vec<McCape>,
+}
+
+impl ProfileDiff {
+    /// Compare two profiles, returning `None` if nothing relevant changed
+    pub fn between(old: &McProfile, new: &McProfile) -> Option<Self> {
+        let old_name = (old.name != new.name).then(|| old.name.clone());
+        let skin_changed = old.active_skin() != new.active_skin();
+        let capes_gained: Vec<McCape> = new
+            .capes
+            .iter()
+            .filter(|cape| !old.capes.iter().any(|c| c.id == cape.id))
+            .cloned()
+            .collect();
+
+        if old_name.is_none() && !skin_changed && capes_gained.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            old_name,
+            new_name: new.name.clone(),
+            skin_changed,
+            capes_gained,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct McSkin {
     pub id: String,
@@ -148,4 +294,317 @@ pub struct McProfileError {
     pub error: String,
     #[serde(default)]
     pub error_message: Option<String>,
+    #[serde(default)]
+    pub details: Option<McProfileErrorDetails>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct McProfileErrorDetails {
+    pub status: String,
+}
+
+/// Yggdrasil agent descriptor
+#[derive(Debug, Clone, Serialize)]
+pub struct YggdrasilAgent {
+    pub name: String,
+    pub version: u32,
+}
+
+impl Default for YggdrasilAgent {
+    fn default() -> Self {
+        Self {
+            name: "Minecraft".to_string(),
+            version: 1,
+        }
+    }
+}
+
+/// Yggdrasil game profile
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct YggdrasilProfile {
+    /// UUID without dashes
+    pub id: String,
+    pub name: String,
+}
+
+/// Yggdrasil user (account) info
+#[derive(Debug, Clone, Deserialize)]
+pub struct YggdrasilUser {
+    pub id: String,
+}
+
+/// Yggdrasil authserver/authenticate request
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilAuthenticateRequest {
+    pub agent: YggdrasilAgent,
+    pub username: String,
+    pub password: String,
+    pub client_token: String,
+    pub request_user: bool,
+}
+
+/// Yggdrasil authserver/refresh request
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilRefreshRequest {
+    pub access_token: String,
+    pub client_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_profile: Option<YggdrasilProfile>,
+    pub request_user: bool,
+}
+
+/// Yggdrasil authserver/validate and authserver/invalidate request
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilTokenRequest {
+    pub access_token: String,
+    pub client_token: String,
+}
+
+/// Yggdrasil authserver/signout request
+#[derive(Debug, Clone, Serialize)]
+pub struct YggdrasilSignoutRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// Yggdrasil authenticate/refresh response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilAuthResponse {
+    pub access_token: String,
+    pub client_token: String,
+    #[serde(default)]
+    pub available_profiles: Vec<YggdrasilProfile>,
+    #[serde(default)]
+    pub selected_profile: Option<YggdrasilProfile>,
+    #[serde(default)]
+    pub user: Option<YggdrasilUser>,
+}
+
+/// Yggdrasil error response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+/// authlib-injector API metadata (served at the API root)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilMetadata {
+    #[serde(default)]
+    pub meta: YggdrasilServerMeta,
+    #[serde(default)]
+    pub skin_domains: Vec<String>,
+    #[serde(default)]
+    pub signature_publickey: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilServerMeta {
+    #[serde(default)]
+    pub server_name: Option<String>,
+    #[serde(default)]
+    pub implementation_name: Option<String>,
+    #[serde(default)]
+    pub implementation_version: Option<String>,
+}
+
+/// Skin model variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkinVariant {
+    /// Steve-style arms (4px wide)
+    Classic,
+    /// Alex-style arms (3px wide)
+    Slim,
+}
+
+impl SkinVariant {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Classic => "classic",
+            Self::Slim => "slim",
+        }
+    }
+}
+
+/// Minecraft profile skin-from-URL request
+#[derive(Debug, Clone, Serialize)]
+pub struct McSkinUrlRequest {
+    pub variant: SkinVariant,
+    pub url: String,
+}
+
+/// Minecraft profile cape selection request
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McCapeRequest {
+    pub cape_id: String,
+}
+
+/// Name change eligibility response
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NameChangeInfo {
+    /// When the name was last changed
+    #[serde(default)]
+    pub changed_at: Option<DateTime<Utc>>,
+    /// When the profile was created
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    pub name_change_allowed: bool,
+}
+
+/// Name availability status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NameAvailability {
+    Available,
+    Duplicate,
+    NotAllowed,
+}
+
+/// Name availability response
+#[derive(Debug, Clone, Deserialize)]
+pub struct NameAvailabilityResponse {
+    pub status: NameAvailability,
+}
+
+/// Entitlement names that grant the Java Edition game
+pub const GAME_ENTITLEMENTS: &[&str] = &["product_minecraft", "game_minecraft"];
+
+/// Entitlements (`/entitlements/mcstore`) and license (`/entitlements/license`) response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntitlementsResponse {
+    #[serde(default)]
+    pub items: Vec<EntitlementItem>,
+    #[serde(default)]
+    pub signature: Option<String>,
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+/// Single entitlement or license item
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EntitlementItem {
+    pub name: String,
+    /// Where the license comes from (e.g. `PURCHASE`, `GAMEPASS`), license listing only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Result of an entitlements check, stored on the session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Entitlements {
+    pub items: Vec<EntitlementItem>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl Entitlements {
+    /// Whether the account owns Minecraft: Java Edition (including through Game Pass)
+    pub fn owns_game(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| GAME_ENTITLEMENTS.contains(&item.name.as_str()))
+    }
+}
+
+/// Player chat-signing certificates response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerCertificatesResponse {
+    pub key_pair: PlayerKeyPair,
+    pub public_key_signature: String,
+    #[serde(default)]
+    pub public_key_signature_v2: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub refreshed_after: DateTime<Utc>,
+}
+
+/// PEM encoded RSA key pair
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerKeyPair {
+    pub private_key: String,
+    pub public_key: String,
+}
+
+/// Player attributes response
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerAttributes {
+    pub privileges: PlayerPrivileges,
+    #[serde(default)]
+    pub profanity_filter_preferences: ProfanityFilterPreferences,
+}
+
+/// What the account is allowed to do in game
+///
+/// Child accounts may have multiplayer or chat disabled by Family settings.
+/// A privilege the response leaves out is `None`, which is not the same as
+/// disabled.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerPrivileges {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub online_chat: Option<Privilege>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multiplayer_server: Option<Privilege>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multiplayer_realms: Option<Privilege>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<Privilege>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Privilege {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfanityFilterPreferences {
+    pub profanity_filter_on: bool,
+}
+
+/// Player attributes update request
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerAttributesUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profanity_filter_preferences: Option<ProfanityFilterPreferences>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_code_from_response() {
+        let response: DeviceCodeResponse = serde_json::from_value(serde_json::json!({
+            "user_code": "ABCD-EFGH",
+            "device_code": "device-123",
+            "verification_uri": "https://www.microsoft.com/link",
+            "expires_in": 900
+        }))
+        .unwrap();
+
+        let device = DeviceCode::from(response);
+        assert_eq!(device.user_code, "ABCD-EFGH");
+        assert_eq!(device.verification_uri, "https://www.microsoft.com/link");
+        assert_eq!(device.device_code, "device-123");
+        assert_eq!(device.message, None);
+        // Microsoft's default when the response leaves it out
+        assert_eq!(device.interval, Duration::from_secs(5));
+        assert!(!device.is_expired());
+        assert!(device.expires_at <= Utc::now() + chrono::Duration::seconds(900));
+    }
 }
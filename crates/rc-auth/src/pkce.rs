@@ -0,0 +1,110 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::errors::{RcAuthError, Result};
+
+/// Number of random bytes in a PKCE code verifier (43 base64url characters)
+const CODE_VERIFIER_LEN: usize = 32;
+
+/// Number of random bytes in a generated OAuth state
+const STATE_LEN: usize = 16;
+
+/// PKCE challenge method sent to the authorize endpoint
+pub const CODE_CHALLENGE_METHOD: &str = "S256";
+
+/// An authorization code login in progress
+///
+/// Returned by [`RcAuthClient::build_authorize_url`](crate::RcAuthClient::build_authorize_url).
+/// Holds the OAuth `state` and the PKCE code verifier, which must be handed back to
+/// [`RcAuthClient::exchange_code`](crate::RcAuthClient::exchange_code). The verifier
+/// never leaves this handle.
+pub struct LoginAttempt {
+    url: Url,
+    state: String,
+    code_verifier: Zeroizing<String>,
+}
+
+impl LoginAttempt {
+    pub(crate) fn new(url: Url, state: String, code_verifier: Zeroizing<String>) -> Self {
+        Self {
+            url,
+            state,
+            code_verifier,
+        }
+    }
+
+    /// Authorization URL the user has to visit
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// OAuth state to verify the redirect against
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub(crate) fn code_verifier(&self) -> &str {
+        &self.code_verifier
+    }
+}
+
+impl std::fmt::Debug for LoginAttempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginAttempt")
+            .field("url", &self.url.as_str())
+            .field("state", &self.state)
+            .field("code_verifier", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// Generate a random PKCE code verifier
+pub(crate) fn generate_code_verifier() -> Result<Zeroizing<String>> {
+    let mut bytes = Zeroizing::new([0u8; CODE_VERIFIER_LEN]);
+    getrandom::fill(bytes.as_mut())
+        .map_err(|e| RcAuthError::Crypto(format!("Failed to generate code verifier: {}", e)))?;
+    Ok(Zeroizing::new(URL_SAFE_NO_PAD.encode(bytes.as_ref())))
+}
+
+/// Generate a random OAuth state
+pub(crate) fn generate_state() -> Result<String> {
+    let mut bytes = [0u8; STATE_LEN];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| RcAuthError::Crypto(format!("Failed to generate state: {}", e)))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Compute the S256 code challenge for a verifier
+pub(crate) fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_challenge_rfc7636_vector() {
+        // Example from RFC 7636 Appendix B
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        assert_eq!(
+            code_challenge(verifier),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_code_verifier_format() {
+        let verifier = generate_code_verifier().unwrap();
+
+        assert_eq!(verifier.len(), 43);
+        assert!(
+            verifier
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_ne!(*verifier, *generate_code_verifier().unwrap());
+    }
+}
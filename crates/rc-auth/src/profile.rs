@@ -0,0 +1,739 @@
+use reqwest::multipart::{Form, Part};
+use tracing::{debug, instrument};
+
+use crate::account::validate_player_name;
+use crate::client::RcAuthClient;
+use crate::errors::{NameChangeError, RcAuthError, Result};
+use crate::models::*;
+use crate::session::Session;
+use crate::store::TokenStore;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// PNG color type for 8-bit RGBA images
+const PNG_COLOR_TYPE_RGBA: u8 = 6;
+
+impl RcAuthClient {
+    /// Upload a skin PNG and make it the active skin
+    ///
+    /// The image is validated first (64x64 or 64x32, 8-bit RGBA).
+    #[instrument(skip(self, mc_access_token, png))]
+    pub async fn upload_skin(
+        &self,
+        mc_access_token: &str,
+        png: &[u8],
+        variant: SkinVariant,
+    ) -> Result<McProfile> {
+        validate_skin_png(png)?;
+
+        debug!("Uploading skin");
+        let response = self
+            .send(|| {
+                let file = Part::bytes(png.to_vec())
+                    .file_name("skin.png")
+                    .mime_str("image/png")
+                    .expect("valid MIME type");
+                let form = Form::new()
+                    .text("variant", variant.as_str())
+                    .part("file", file);
+
+                self.http
+                    .post(self.config.endpoints.mc_profile_skins())
+                    .bearer_auth(mc_access_token)
+                    .multipart(form)
+            })
+            .await?;
+
+        read_profile(response).await
+    }
+
+    /// Set the active skin from a public image URL
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn set_skin_url(
+        &self,
+        mc_access_token: &str,
+        url: &str,
+        variant: SkinVariant,
+    ) -> Result<McProfile> {
+        let request = McSkinUrlRequest {
+            variant,
+            url: url.to_string(),
+        };
+
+        debug!("Setting skin from URL");
+        let response = self
+            .send(|| {
+                self.http
+                    .post(self.config.endpoints.mc_profile_skins())
+                    .bearer_auth(mc_access_token)
+                    .json(&request)
+            })
+            .await?;
+
+        read_profile(response).await
+    }
+
+    /// Reset the active skin to the default one
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn reset_skin(&self, mc_access_token: &str) -> Result<McProfile> {
+        debug!("Resetting skin");
+        let response = self
+            .send(|| {
+                self.http
+                    .delete(self.config.endpoints.mc_profile_active_skin())
+                    .bearer_auth(mc_access_token)
+            })
+            .await?;
+
+        read_profile(response).await
+    }
+
+    /// Show one of the profile's capes
+    ///
+    /// The cape must be one of `session.profile.capes`. The refreshed profile is
+    /// saved into the session stored in `store` and returned.
+    #[instrument(skip(self, store, session))]
+    pub async fn show_cape(
+        &self,
+        store: &dyn TokenStore,
+        session: &Session,
+        cape_id: &str,
+    ) -> Result<McProfile> {
+        if !session.profile.capes.iter().any(|c| c.id == cape_id) {
+            return Err(RcAuthError::CapeNotOwned(cape_id.to_string()));
+        }
+
+        let request = McCapeRequest {
+            cape_id: cape_id.to_string(),
+        };
+
+        debug!("Showing cape");
+        let response = self
+            .send(|| {
+                self.http
+                    .put(self.config.endpoints.mc_profile_active_cape())
+                    .bearer_auth(&session.mc.access_token)
+                    .json(&request)
+            })
+            .await?;
+
+        let profile = read_profile(response).await?;
+        save_profile(store, session, &profile).await?;
+        Ok(profile)
+    }
+
+    /// Hide the currently shown cape
+    ///
+    /// The refreshed profile is saved into the session stored in `store` and returned.
+    #[instrument(skip(self, store, session))]
+    pub async fn hide_cape(&self, store: &dyn TokenStore, session: &Session) -> Result<McProfile> {
+        debug!("Hiding cape");
+        let response = self
+            .send(|| {
+                self.http
+                    .delete(self.config.endpoints.mc_profile_active_cape())
+                    .bearer_auth(&session.mc.access_token)
+            })
+            .await?;
+
+        let profile = read_profile(response).await?;
+        save_profile(store, session, &profile).await?;
+        Ok(profile)
+    }
+
+    /// Check whether the profile name can be changed right now
+    ///
+    /// `NameChangeInfo::name_change_allowed` is false while the name is on
+    /// cooldown.
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn check_name_change_eligibility(
+        &self,
+        mc_access_token: &str,
+    ) -> Result<NameChangeInfo> {
+        debug!("Checking name change eligibility");
+        let response = self
+            .send(|| {
+                self.http
+                    .get(self.config.endpoints.mc_profile_namechange())
+                    .bearer_auth(mc_access_token)
+            })
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(RcAuthError::Http {
+                status,
+                body_snippet: body.chars().take(200).collect(),
+            });
+        }
+
+        let info: NameChangeInfo = response.json().await?;
+        Ok(info)
+    }
+
+    /// Check whether a name is available
+    ///
+    /// Returns `NameChangeError::Duplicate` or `NameChangeError::NotAllowed` if
+    /// the name can't be taken.
+    #[instrument(skip(self, mc_access_token))]
+    pub async fn check_name_available(&self, mc_access_token: &str, name: &str) -> Result<()> {
+        validate_player_name(name)?;
+
+        debug!("Checking name availability");
+        let response = self
+            .send(|| {
+                self.http
+                    .get(self.config.endpoints.mc_profile_name_available(name))
+                    .bearer_auth(mc_access_token)
+            })
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(RcAuthError::Http {
+                status,
+                body_snippet: body.chars().take(200).collect(),
+            });
+        }
+
+        let availability: NameAvailabilityResponse = response.json().await?;
+        match availability.status {
+            NameAvailability::Available => Ok(()),
+            NameAvailability::Duplicate => Err(NameChangeError::Duplicate.into()),
+            NameAvailability::NotAllowed => Err(NameChangeError::NotAllowed.into()),
+        }
+    }
+
+    /// Change the profile name
+    ///
+    /// The refreshed profile is saved into the session stored in `store` and
+    /// returned.
+    #[instrument(skip(self, store, session))]
+    pub async fn change_name(
+        &self,
+        store: &dyn TokenStore,
+        session: &Session,
+        name: &str,
+    ) -> Result<McProfile> {
+        validate_player_name(name)?;
+
+        debug!("Changing profile name");
+        let response = self
+            .send(|| {
+                self.http
+                    .put(self.config.endpoints.mc_profile_name(name))
+                    .bearer_auth(&session.mc.access_token)
+            })
+            .await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::BAD_REQUEST {
+            return Err(NameChangeError::NotAllowed.into());
+        }
+        if status == reqwest::StatusCode::FORBIDDEN {
+            let body = response.text().await.unwrap_or_default();
+            let details = serde_json::from_str::<McProfileError>(&body)
+                .ok()
+                .and_then(|e| e.details);
+            let error = match details.as_ref().map(|d| d.status.as_str()) {
+                Some("DUPLICATE") => NameChangeError::Duplicate.into(),
+                Some("NOT_ALLOWED") => NameChangeError::NotAllowed.into(),
+                // A cooldown carries no details, so confirm it with the
+                // eligibility endpoint rather than guessing from the status
+                _ => match self
+                    .check_name_change_eligibility(&session.mc.access_token)
+                    .await
+                {
+                    Ok(info) if !info.name_change_allowed => NameChangeError::Cooldown {
+                        changed_at: info.changed_at,
+                    }
+                    .into(),
+                    _ => RcAuthError::Http {
+                        status,
+                        body_snippet: body.chars().take(200).collect(),
+                    },
+                },
+            };
+            return Err(error);
+        }
+
+        let profile = read_profile(response).await?;
+        save_profile(store, session, &profile).await?;
+        Ok(profile)
+    }
+}
+
+/// Replace the profile of the stored session
+///
+/// Only the profile is written, so tokens refreshed since `session` was loaded
+/// are kept. Nothing is saved if the session isn't stored.
+async fn save_profile(
+    store: &dyn TokenStore,
+    session: &Session,
+    profile: &McProfile,
+) -> Result<()> {
+    let profile = profile.clone();
+    store
+        .update_session(
+            session.account_key(),
+            Box::new(move |stored| stored.profile = profile),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Read the updated profile returned by a profile mutation
+async fn read_profile(response: reqwest::Response) -> Result<McProfile> {
+    let status = response.status();
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(RcAuthError::Http {
+            status,
+            body_snippet: body.chars().take(200).collect(),
+        });
+    }
+
+    Ok(response.json().await?)
+}
+
+/// Check that a PNG is a valid skin (64x64 or legacy 64x32, 8-bit RGBA)
+pub fn validate_skin_png(png: &[u8]) -> Result<()> {
+    // Signature (8) + IHDR length (4) + "IHDR" (4) + width (4) + height (4)
+    // + bit depth (1) + color type (1)
+    if png.len() < 26 || !png.starts_with(PNG_SIGNATURE) || &png[12..16] != b"IHDR" {
+        return Err(RcAuthError::InvalidSkin("not a PNG image".to_string()));
+    }
+
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    let bit_depth = png[24];
+    let color_type = png[25];
+
+    if width != 64 || !(height == 64 || height == 32) {
+        return Err(RcAuthError::InvalidSkin(format!(
+            "expected 64x64 or 64x32, got {}x{}",
+            width, height
+        )));
+    }
+
+    if color_type != PNG_COLOR_TYPE_RGBA || bit_depth != 8 {
+        return Err(RcAuthError::InvalidSkin(
+            "expected 8-bit RGBA color".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{mock_client, test_session};
+    use crate::store::MemoryTokenStore;
+    use serde_json::json;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    /// Minimal PNG header (signature + IHDR) for the given format
+    fn png_header(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&width.to_be_bytes());
+        png.extend_from_slice(&height.to_be_bytes());
+        png.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        png
+    }
+
+    fn profile_json(skin_url: &str, variant: &str) -> serde_json::Value {
+        json!({
+            "id": "069a79f444e94726a5befca90e38aaf5",
+            "name": "Notch",
+            "skins": [{
+                "id": "skin-id",
+                "state": "ACTIVE",
+                "url": skin_url,
+                "variant": variant
+            }],
+            "capes": []
+        })
+    }
+
+    #[test]
+    fn test_validate_skin_png() {
+        assert!(validate_skin_png(&png_header(64, 64, 8, 6)).is_ok());
+        assert!(validate_skin_png(&png_header(64, 32, 8, 6)).is_ok());
+        assert!(validate_skin_png(&png_header(128, 128, 8, 6)).is_err());
+        assert!(validate_skin_png(&png_header(64, 64, 8, 2)).is_err());
+        assert!(validate_skin_png(&png_header(64, 64, 16, 6)).is_err());
+        assert!(validate_skin_png(b"GIF89a").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upload_skin() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/minecraft/profile/skins"))
+            .and(header("Authorization", "Bearer mc-token"))
+            .and(|request: &Request| {
+                // The body isn't UTF-8 because of the PNG bytes
+                let contains =
+                    |needle: &[u8]| request.body.windows(needle.len()).any(|w| w == needle);
+                contains(b"name=\"variant\"\r\n\r\nslim") && contains(b"filename=\"skin.png\"")
+            })
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(profile_json("http://textures.example/new", "SLIM")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let profile = client
+            .upload_skin("mc-token", &png_header(64, 64, 8, 6), SkinVariant::Slim)
+            .await
+            .unwrap();
+        assert_eq!(profile.skins[0].variant, "SLIM");
+        assert_eq!(profile.skins[0].url, "http://textures.example/new");
+    }
+
+    #[tokio::test]
+    async fn test_upload_invalid_skin_is_not_sent() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let result = client
+            .upload_skin("mc-token", &png_header(32, 32, 8, 6), SkinVariant::Classic)
+            .await;
+        assert!(matches!(result, Err(RcAuthError::InvalidSkin(_))));
+    }
+
+    #[tokio::test]
+    async fn test_set_skin_url_and_reset() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("POST"))
+            .and(path("/minecraft/profile/skins"))
+            .and(body_json(json!({
+                "variant": "classic",
+                "url": "https://example.com/skin.png"
+            })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(profile_json("http://textures.example/url", "CLASSIC")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/minecraft/profile/skins/active"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(profile_json("http://textures.example/steve", "CLASSIC")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let profile = client
+            .set_skin_url(
+                "mc-token",
+                "https://example.com/skin.png",
+                SkinVariant::Classic,
+            )
+            .await
+            .unwrap();
+        assert_eq!(profile.skins[0].url, "http://textures.example/url");
+
+        let profile = client.reset_skin("mc-token").await.unwrap();
+        assert_eq!(profile.skins[0].url, "http://textures.example/steve");
+    }
+
+    fn cape(id: &str, state: &str) -> McCape {
+        McCape {
+            id: id.to_string(),
+            state: state.to_string(),
+            url: format!("http://textures.example/{}", id),
+            alias: Some(id.to_uppercase()),
+        }
+    }
+
+    fn profile_with_capes(capes: &[McCape]) -> McProfile {
+        McProfile {
+            capes: capes.to_vec(),
+            ..test_session().profile
+        }
+    }
+
+    #[tokio::test]
+    async fn test_show_and_hide_cape() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+        let store = MemoryTokenStore::new();
+
+        let mut session = test_session();
+        session.profile = profile_with_capes(&[cape("migrator", "INACTIVE")]);
+        store.save(session.account_key(), &session).await.unwrap();
+
+        let shown = profile_with_capes(&[cape("migrator", "ACTIVE")]);
+        Mock::given(method("PUT"))
+            .and(path("/minecraft/profile/capes/active"))
+            .and(header("Authorization", "Bearer mc-token"))
+            .and(body_json(json!({ "capeId": "migrator" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&shown))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let hidden = profile_with_capes(&[cape("migrator", "INACTIVE")]);
+        Mock::given(method("DELETE"))
+            .and(path("/minecraft/profile/capes/active"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&hidden))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let profile = client
+            .show_cape(&store, &session, "migrator")
+            .await
+            .unwrap();
+        assert_eq!(profile.active_cape().unwrap().id, "migrator");
+        let stored = store.load(session.account_key()).await.unwrap();
+        assert_eq!(stored.profile, shown);
+
+        let profile = client.hide_cape(&store, &stored).await.unwrap();
+        assert!(profile.active_cape().is_none());
+        let stored = store.load(session.account_key()).await.unwrap();
+        assert_eq!(stored.profile, hidden);
+    }
+
+    #[tokio::test]
+    async fn test_show_cape_keeps_refreshed_tokens() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+        let store = MemoryTokenStore::new();
+
+        let mut session = test_session();
+        session.profile = profile_with_capes(&[cape("migrator", "INACTIVE")]);
+
+        // Refreshed elsewhere after the caller loaded `session`
+        let mut refreshed = session.clone();
+        refreshed.ms.refresh_token = Some("rotated-refresh".to_string());
+        store.save(session.account_key(), &refreshed).await.unwrap();
+
+        let shown = profile_with_capes(&[cape("migrator", "ACTIVE")]);
+        Mock::given(method("PUT"))
+            .and(path("/minecraft/profile/capes/active"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&shown))
+            .mount(&server)
+            .await;
+
+        client
+            .show_cape(&store, &session, "migrator")
+            .await
+            .unwrap();
+
+        let stored = store.load(session.account_key()).await.unwrap();
+        assert_eq!(stored.profile, shown);
+        assert_eq!(stored.ms.refresh_token.as_deref(), Some("rotated-refresh"));
+    }
+
+    #[tokio::test]
+    async fn test_show_unowned_cape() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+        let store = MemoryTokenStore::new();
+
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let result = client.show_cape(&store, &test_session(), "vanilla").await;
+        assert!(matches!(result, Err(RcAuthError::CapeNotOwned(id)) if id == "vanilla"));
+    }
+
+    #[tokio::test]
+    async fn test_name_change_eligibility() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile/namechange"))
+            .and(header("Authorization", "Bearer allowed"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "changedAt": "2020-01-01T00:00:00Z",
+                "createdAt": "2015-01-01T00:00:00Z",
+                "nameChangeAllowed": true
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile/namechange"))
+            .and(header("Authorization", "Bearer cooldown"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "changedAt": "2026-10-01T00:00:00Z",
+                "createdAt": "2015-01-01T00:00:00Z",
+                "nameChangeAllowed": false
+            })))
+            .mount(&server)
+            .await;
+
+        let info = client
+            .check_name_change_eligibility("allowed")
+            .await
+            .unwrap();
+        assert!(info.name_change_allowed);
+
+        let info = client
+            .check_name_change_eligibility("cooldown")
+            .await
+            .unwrap();
+        assert!(!info.name_change_allowed);
+        assert!(info.changed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_name_availability() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+
+        for (name, status) in [
+            ("Free", "AVAILABLE"),
+            ("Taken", "DUPLICATE"),
+            ("Rude", "NOT_ALLOWED"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!("/minecraft/profile/name/{}/available", name)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": status })))
+                .mount(&server)
+                .await;
+        }
+
+        assert!(
+            client
+                .check_name_available("mc-token", "Free")
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            client.check_name_available("mc-token", "Taken").await,
+            Err(RcAuthError::NameChange(NameChangeError::Duplicate))
+        ));
+        assert!(matches!(
+            client.check_name_available("mc-token", "Rude").await,
+            Err(RcAuthError::NameChange(NameChangeError::NotAllowed))
+        ));
+        assert!(matches!(
+            client.check_name_available("mc-token", "bad name").await,
+            Err(RcAuthError::InvalidPlayerName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_change_name_updates_store() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+        let store = MemoryTokenStore::new();
+
+        let session = test_session();
+
+        // Refreshed elsewhere after the caller loaded `session`
+        let mut refreshed = session.clone();
+        refreshed.ms.refresh_token = Some("rotated-refresh".to_string());
+        store.save(session.account_key(), &refreshed).await.unwrap();
+
+        let renamed = McProfile {
+            name: "NewName".to_string(),
+            ..session.profile.clone()
+        };
+        Mock::given(method("PUT"))
+            .and(path("/minecraft/profile/name/NewName"))
+            .and(header("Authorization", "Bearer mc-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&renamed))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let profile = client
+            .change_name(&store, &session, "NewName")
+            .await
+            .unwrap();
+        assert_eq!(profile.name, "NewName");
+
+        let stored = store.load(session.account_key()).await.unwrap();
+        assert_eq!(stored.profile.name, "NewName");
+        assert_eq!(stored.ms.refresh_token.as_deref(), Some("rotated-refresh"));
+    }
+
+    #[tokio::test]
+    async fn test_change_name_errors() {
+        let server = MockServer::start().await;
+        let client = mock_client(&server);
+        let store = MemoryTokenStore::new();
+        let session = test_session();
+
+        Mock::given(method("PUT"))
+            .and(path("/minecraft/profile/name/Taken"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                "error": "FORBIDDEN",
+                "details": { "status": "DUPLICATE" }
+            })))
+            .mount(&server)
+            .await;
+        for name in ["TooSoon", "Forbidden"] {
+            Mock::given(method("PUT"))
+                .and(path(format!("/minecraft/profile/name/{}", name)))
+                .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                    "error": "FORBIDDEN"
+                })))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile/namechange"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "changedAt": "2026-10-01T12:00:00Z",
+                "nameChangeAllowed": false
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/minecraft/profile/namechange"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "nameChangeAllowed": true
+            })))
+            .mount(&server)
+            .await;
+
+        assert!(matches!(
+            client.change_name(&store, &session, "Taken").await,
+            Err(RcAuthError::NameChange(NameChangeError::Duplicate))
+        ));
+        assert!(matches!(
+            client.change_name(&store, &session, "TooSoon").await,
+            Err(RcAuthError::NameChange(NameChangeError::Cooldown {
+                changed_at: Some(_)
+            }))
+        ));
+        // Forbidden for another reason: not reported as a cooldown
+        assert!(matches!(
+            client.change_name(&store, &session, "Forbidden").await,
+            Err(RcAuthError::Http { status, .. }) if status == reqwest::StatusCode::FORBIDDEN
+        ));
+        assert!(store.load(session.account_key()).await.is_none());
+    }
+}
@@ -0,0 +1,292 @@
+use std::time::Duration;
+
+use reqwest::{RequestBuilder, Response, StatusCode, header::RETRY_AFTER};
+use tracing::{Instrument, debug, info_span, warn};
+
+use crate::config::RetryPolicy;
+use crate::errors::Result;
+
+/// Whether a request may be sent again after its response was lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Idempotency {
+    Idempotent,
+    /// Spends a single-use credential such as an authorization code or refresh
+    /// token: a timed-out attempt may have used it up on the server
+    SingleUse,
+}
+
+/// Send a request, retrying transient failures according to `policy`
+///
+/// Connect errors, timeouts, 5xx and 429 responses are retried with exponential
+/// backoff and jitter, honoring `Retry-After` when the server sends it. Timeouts
+/// aren't retried for `SingleUse` requests. Any other response (including 4xx
+/// errors such as `invalid_grant` or XSTS denials) is returned to the caller
+/// untouched. When retries are exhausted the last response or error is returned.
+///
+/// `build` is called once per attempt because request bodies can't be reused.
+pub(crate) async fn send_with_retry<F>(
+    policy: &RetryPolicy,
+    idempotency: Idempotency,
+    build: F,
+) -> Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut attempt = 0;
+
+    loop {
+        let (client, request) = build().build_split();
+        let request = request?;
+        let span = info_span!(
+            "http_attempt",
+            attempt,
+            method = %request.method(),
+            url = %request.url().as_str(),
+        );
+
+        let result = client.execute(request).instrument(span.clone()).await;
+
+        let retry_after = match &result {
+            Ok(response) if is_retryable_status(response.status()) => parse_retry_after(response),
+            // A connect error means the request never reached the server
+            Err(e) if e.is_connect() => None,
+            Err(e) if e.is_timeout() && idempotency == Idempotency::Idempotent => None,
+            _ => return Ok(result?),
+        };
+
+        if attempt >= policy.max_retries {
+            debug!(parent: &span, "Retries exhausted");
+            return Ok(result?);
+        }
+
+        let delay = retry_after
+            .unwrap_or_else(|| backoff_delay(policy, attempt))
+            .min(policy.max_delay);
+
+        match &result {
+            Ok(response) => warn!(
+                parent: &span,
+                status = %response.status(),
+                ?delay,
+                "Transient HTTP status, retrying"
+            ),
+            Err(e) => warn!(parent: &span, error = %e, ?delay, "Transient network error, retrying"),
+        }
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Parse `Retry-After` as delay-seconds or an HTTP date
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+/// Exponential backoff with up to 50% random jitter
+fn backoff_delay(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let exponential = policy
+        .base_delay
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(policy.max_delay);
+
+    let mut random = [0u8; 8];
+    let jitter = match getrandom::fill(&mut random) {
+        Ok(()) => u64::from_le_bytes(random) as f64 / u64::MAX as f64 * 0.5,
+        Err(_) => 0.0,
+    };
+
+    exponential.mul_f64(1.0 + jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http = reqwest::Client::new();
+        let url = format!("{}/flaky", server.uri());
+        let response = send_with_retry(&fast_policy(), Idempotency::Idempotent, || http.get(&url))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_honors_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let policy = RetryPolicy {
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(60),
+            ..fast_policy()
+        };
+        let http = reqwest::Client::new();
+        let response = tokio::time::timeout(
+            Duration::from_secs(5),
+            send_with_retry(&policy, Idempotency::Idempotent, || http.get(server.uri())),
+        )
+        .await
+        .expect("Retry-After: 0 should override the base delay")
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(400).set_body_string(r#"{"error":"invalid_grant"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http = reqwest::Client::new();
+        let response = send_with_retry(&fast_policy(), Idempotency::Idempotent, || {
+            http.post(server.uri())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(4)
+            .mount(&server)
+            .await;
+
+        let http = reqwest::Client::new();
+        let response = send_with_retry(&fast_policy(), Idempotency::Idempotent, || {
+            http.get(server.uri())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_retries_timeouts_only_when_idempotent() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/profile"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .expect(4)
+            .mount(&server)
+            .await;
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let token_url = format!("{}/token", server.uri());
+        let profile_url = format!("{}/profile", server.uri());
+
+        let result = send_with_retry(&fast_policy(), Idempotency::SingleUse, || {
+            http.post(&token_url)
+        })
+        .await;
+        assert!(matches!(result, Err(crate::RcAuthError::Network(e)) if e.is_timeout()));
+
+        let result = send_with_retry(&fast_policy(), Idempotency::Idempotent, || {
+            http.get(&profile_url)
+        })
+        .await;
+        assert!(matches!(result, Err(crate::RcAuthError::Network(e)) if e.is_timeout()));
+    }
+
+    #[tokio::test]
+    async fn test_retries_connect_errors() {
+        // Bind and drop a listener to get a port nothing is listening on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let http = reqwest::Client::new();
+        let result =
+            send_with_retry(&fast_policy(), Idempotency::Idempotent, || http.get(&url)).await;
+
+        assert!(matches!(result, Err(crate::RcAuthError::Network(e)) if e.is_connect()));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+
+        let first = backoff_delay(&policy, 0);
+        assert!(first >= Duration::from_millis(100) && first <= Duration::from_millis(150));
+
+        let third = backoff_delay(&policy, 2);
+        assert!(third >= Duration::from_millis(400) && third <= Duration::from_millis(600));
+
+        let capped = backoff_delay(&policy, 20);
+        assert!(capped <= Duration::from_millis(1500));
+    }
+}
@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::models::McProfile;
+use crate::models::{Entitlements, McProfile, PlayerCertificatesResponse, deserialize_timestamp};
 
 /// Complete authentication session with all tokens and profile
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -15,6 +15,12 @@ pub struct Session {
     pub xuid: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gamertag: Option<String>,
+    /// Last entitlements check, `None` if it never succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entitlements: Option<Entitlements>,
+    /// Chat-signing key pair, `None` until first fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificates: Option<PlayerCertificates>,
 }
 
 impl Session {
@@ -27,6 +33,11 @@ impl Session {
     pub fn account_key(&self) -> &str {
         &self.profile.id
     }
+
+    /// Whether the account owns the game, `None` if entitlements are unknown
+    pub fn owns_game(&self) -> Option<bool> {
+        self.entitlements.as_ref().map(Entitlements::owns_game)
+    }
 }
 
 /// Microsoft OAuth tokens
@@ -57,7 +68,17 @@ impl MsTokens {
 pub struct XblToken {
     pub token: String,
     pub uhs: String,
-    pub not_after: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl XblToken {
+    /// Check if the token is expired or about to expire
+    ///
+    /// A token without a known expiry is treated as expired.
+    pub fn is_expired(&self) -> bool {
+        self.not_after.is_none_or(is_within_skew)
+    }
 }
 
 /// XSTS token
@@ -65,7 +86,17 @@ pub struct XblToken {
 pub struct XstsToken {
     pub token: String,
     pub uhs: String,
-    pub not_after: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl XstsToken {
+    /// Check if the token is expired or about to expire
+    ///
+    /// A token without a known expiry is treated as expired.
+    pub fn is_expired(&self) -> bool {
+        self.not_after.is_none_or(is_within_skew)
+    }
 }
 
 /// Minecraft access token
@@ -85,9 +116,65 @@ impl McToken {
     }
 
     pub fn is_expired(&self) -> bool {
-        use crate::config::TOKEN_EXPIRY_SKEW;
-        let skew_duration =
-            chrono::Duration::from_std(TOKEN_EXPIRY_SKEW).unwrap_or(chrono::Duration::seconds(300));
-        Utc::now() + skew_duration >= self.expires_at
+        is_within_skew(self.expires_at)
+    }
+}
+
+/// Player chat-signing key pair and Mojang-signed certificates
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerCertificates {
+    /// PEM encoded RSA private key
+    pub private_key: String,
+    /// PEM encoded RSA public key
+    pub public_key: String,
+    pub public_key_signature: String,
+    pub public_key_signature_v2: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    /// Time after which Mojang recommends fetching new certificates
+    pub refreshed_after: DateTime<Utc>,
+}
+
+impl PlayerCertificates {
+    pub fn is_expired(&self) -> bool {
+        is_within_skew(self.expires_at)
+    }
+
+    /// Check if the certificates are due for refresh or about to expire
+    pub fn needs_refresh(&self) -> bool {
+        Utc::now() >= self.refreshed_after || self.is_expired()
+    }
+}
+
+impl std::fmt::Debug for PlayerCertificates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlayerCertificates")
+            .field("private_key", &"[REDACTED]")
+            .field("public_key", &self.public_key)
+            .field("public_key_signature", &self.public_key_signature)
+            .field("public_key_signature_v2", &self.public_key_signature_v2)
+            .field("expires_at", &self.expires_at)
+            .field("refreshed_after", &self.refreshed_after)
+            .finish()
     }
 }
+
+impl From<PlayerCertificatesResponse> for PlayerCertificates {
+    fn from(response: PlayerCertificatesResponse) -> Self {
+        Self {
+            private_key: response.key_pair.private_key,
+            public_key: response.key_pair.public_key,
+            public_key_signature: response.public_key_signature,
+            public_key_signature_v2: response.public_key_signature_v2,
+            expires_at: response.expires_at,
+            refreshed_after: response.refreshed_after,
+        }
+    }
+}
+
+/// Check if `expires_at` falls within `TOKEN_EXPIRY_SKEW` from now
+fn is_within_skew(expires_at: DateTime<Utc>) -> bool {
+    use crate::config::TOKEN_EXPIRY_SKEW;
+    let skew_duration =
+        chrono::Duration::from_std(TOKEN_EXPIRY_SKEW).unwrap_or(chrono::Duration::seconds(300));
+    Utc::now() + skew_duration >= expires_at
+}
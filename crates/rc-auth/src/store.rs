@@ -1,50 +1,146 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+use tokio::sync::broadcast;
+
+use crate::account::Account;
 use crate::errors::Result;
 use crate::session::Session;
 
-/// Trait for storing and retrieving authentication sessions
+/// Number of events buffered for slow subscribers before they start lagging
+pub(crate) const STORE_EVENT_CAPACITY: usize = 64;
+
+/// Change applied to a stored account by [`TokenStore::update_account`]
+pub type AccountUpdate = Box<dyn for<'a> FnOnce(&'a mut Account) + Send>;
+
+/// Change applied to a stored session by [`TokenStore::update_session`]
+pub type SessionUpdate = Box<dyn for<'a> FnOnce(&'a mut Session) + Send>;
+
+/// Change made to a token store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreEvent {
+    /// An account was created or updated
+    Saved { account_key: String },
+    /// An account was removed
+    Removed { account_key: String },
+    /// The encryption key was rotated and every account re-encrypted
+    Rotated,
+}
+
+/// Trait for storing and retrieving accounts (Microsoft sessions and offline players)
 #[async_trait::async_trait]
 pub trait TokenStore: Send + Sync {
-    /// Load a session by account key (UUID)
-    async fn load(&self, account_key: &str) -> Option<Session>;
+    /// Load an account of any kind by account key (UUID)
+    async fn load_account(&self, account_key: &str) -> Option<Account>;
 
-    /// Save a session by account key (UUID)
-    async fn save(&self, account_key: &str, session: &Session) -> Result<()>;
+    /// Save an account of any kind by account key (UUID)
+    async fn save_account(&self, account_key: &str, account: &Account) -> Result<()>;
 
-    /// Remove a session by account key (UUID)
+    /// Remove an account by account key (UUID)
     async fn remove(&self, account_key: &str) -> Result<()>;
 
     /// List all stored account keys
     async fn list_accounts(&self) -> Vec<String>;
+
+    /// Apply `update` to the stored account and save it
+    ///
+    /// Works on the current stored copy rather than the caller's, so tokens
+    /// rotated by another writer in the meantime aren't overwritten. Stores
+    /// with a lock hold it for the whole update. Returns the updated account,
+    /// or `None` without saving if the account isn't stored.
+    async fn update_account(
+        &self,
+        account_key: &str,
+        update: AccountUpdate,
+    ) -> Result<Option<Account>> {
+        let Some(mut account) = self.load_account(account_key).await else {
+            return Ok(None);
+        };
+        update(&mut account);
+        self.save_account(account_key, &account).await?;
+        Ok(Some(account))
+    }
+
+    /// Load a Microsoft session by account key (UUID)
+    ///
+    /// Returns `None` if the key belongs to an offline account.
+    async fn load(&self, account_key: &str) -> Option<Session> {
+        self.load_account(account_key).await?.into_session()
+    }
+
+    /// Save a Microsoft session by account key (UUID)
+    async fn save(&self, account_key: &str, session: &Session) -> Result<()> {
+        self.save_account(account_key, &Account::from(session.clone()))
+            .await
+    }
+
+    /// Apply `update` to the stored Microsoft session and save it
+    ///
+    /// See [`TokenStore::update_account`]. Returns `None` if no Microsoft
+    /// session is stored under the key.
+    async fn update_session(
+        &self,
+        account_key: &str,
+        update: SessionUpdate,
+    ) -> Result<Option<Session>> {
+        let updated = self
+            .update_account(
+                account_key,
+                Box::new(move |account| {
+                    if let Account::Microsoft(session) = account {
+                        update(session);
+                    }
+                }),
+            )
+            .await?;
+        Ok(updated.and_then(Account::into_session))
+    }
+
+    /// Subscribe to changes made through this store
+    ///
+    /// Returns `None` if the store doesn't emit events.
+    fn subscribe(&self) -> Option<broadcast::Receiver<StoreEvent>> {
+        None
+    }
 }
 
 /// In-memory token store for testing and simple use cases
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct MemoryTokenStore {
-    sessions: Arc<RwLock<HashMap<String, Session>>>,
+    sessions: Arc<RwLock<HashMap<String, Account>>>,
+    events: broadcast::Sender<StoreEvent>,
 }
 
 impl MemoryTokenStore {
     pub fn new() -> Self {
         Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
+            events: broadcast::channel(STORE_EVENT_CAPACITY).0,
         }
     }
 }
 
+impl Default for MemoryTokenStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait::async_trait]
 impl TokenStore for MemoryTokenStore {
-    async fn load(&self, account_key: &str) -> Option<Session> {
+    async fn load_account(&self, account_key: &str) -> Option<Account> {
         self.sessions.read().ok()?.get(account_key).cloned()
     }
 
-    async fn save(&self, account_key: &str, session: &Session) -> Result<()> {
+    async fn save_account(&self, account_key: &str, account: &Account) -> Result<()> {
         self.sessions
             .write()
             .map_err(|_| crate::errors::RcAuthError::InvalidResponse("Lock poisoned".to_string()))?
-            .insert(account_key.to_string(), session.clone());
+            .insert(account_key.to_string(), account.clone());
+
+        let _ = self.events.send(StoreEvent::Saved {
+            account_key: account_key.to_string(),
+        });
         Ok(())
     }
 
@@ -53,9 +149,35 @@ impl TokenStore for MemoryTokenStore {
             .write()
             .map_err(|_| crate::errors::RcAuthError::InvalidResponse("Lock poisoned".to_string()))?
             .remove(account_key);
+
+        let _ = self.events.send(StoreEvent::Removed {
+            account_key: account_key.to_string(),
+        });
         Ok(())
     }
 
+    async fn update_account(
+        &self,
+        account_key: &str,
+        update: AccountUpdate,
+    ) -> Result<Option<Account>> {
+        let updated = {
+            let mut sessions = self.sessions.write().map_err(|_| {
+                crate::errors::RcAuthError::InvalidResponse("Lock poisoned".to_string())
+            })?;
+            let Some(account) = sessions.get_mut(account_key) else {
+                return Ok(None);
+            };
+            update(account);
+            account.clone()
+        };
+
+        let _ = self.events.send(StoreEvent::Saved {
+            account_key: account_key.to_string(),
+        });
+        Ok(Some(updated))
+    }
+
     async fn list_accounts(&self) -> Vec<String> {
         self.sessions
             .read()
@@ -63,4 +185,8 @@ impl TokenStore for MemoryTokenStore {
             .map(|s| s.keys().cloned().collect())
             .unwrap_or_default()
     }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<StoreEvent>> {
+        Some(self.events.subscribe())
+    }
 }
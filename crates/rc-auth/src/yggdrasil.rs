@@ -0,0 +1,558 @@
+use std::path::Path;
+
+use base64::Engine;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use url::Url;
+
+use crate::client::build_http_client;
+use crate::config::RcAuthConfig;
+use crate::errors::{RcAuthError, Result};
+use crate::models::*;
+use crate::retry::{self, Idempotency};
+
+/// Header pointing from a server's home page to its authlib-injector API root
+pub const API_LOCATION_HEADER: &str = "X-Authlib-Injector-API-Location";
+
+/// Client for Yggdrasil-protocol (authlib-injector) authentication servers
+#[derive(Debug, Clone)]
+pub struct YggdrasilClient {
+    config: RcAuthConfig,
+    http: Client,
+    api_root: Url,
+    metadata: Option<String>,
+}
+
+/// Session on a third-party Yggdrasil server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct YggdrasilSession {
+    /// authlib-injector API root (with trailing slash)
+    pub api_root: String,
+    /// Raw API metadata, passed to authlib-injector to skip a request at launch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    pub access_token: String,
+    pub client_token: String,
+    pub profile: YggdrasilProfile,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+}
+
+impl YggdrasilSession {
+    /// Get the account key (profile UUID) for storage
+    pub fn account_key(&self) -> &str {
+        &self.profile.id
+    }
+
+    /// JVM arguments that load authlib-injector for this server
+    pub fn jvm_args(&self, agent_jar: &Path) -> Vec<String> {
+        let mut args = vec![format!(
+            "-javaagent:{}={}",
+            agent_jar.display(),
+            self.api_root
+        )];
+
+        if let Some(metadata) = &self.metadata {
+            args.push(format!(
+                "-Dauthlibinjector.yggdrasil.prefetched={}",
+                base64::engine::general_purpose::STANDARD.encode(metadata)
+            ));
+        }
+
+        args
+    }
+}
+
+impl YggdrasilClient {
+    /// Create a client for a known API root, without discovery
+    pub fn new(config: RcAuthConfig, api_root: Url) -> Result<Self> {
+        let http = build_http_client(&config)?;
+
+        Ok(Self {
+            config,
+            http,
+            api_root: with_trailing_slash(api_root),
+            metadata: None,
+        })
+    }
+
+    /// Discover the API root of a server and fetch its metadata
+    ///
+    /// Follows the `X-Authlib-Injector-API-Location` header if the server sends
+    /// one, so users can enter the server's home page instead of the API URL.
+    #[instrument(skip(config))]
+    pub async fn discover(config: RcAuthConfig, server_url: &str) -> Result<Self> {
+        let mut client = Self::new(config, Url::parse(server_url)?)?;
+        let mut url = Url::parse(server_url)?;
+
+        debug!("Discovering Yggdrasil API root");
+        let mut response = client.send(|| client.http.get(url.clone())).await?;
+
+        let location = response
+            .headers()
+            .get(API_LOCATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| url.join(v))
+            .transpose()?;
+
+        if let Some(location) = location
+            && location != url
+        {
+            debug!("Following {} to {}", API_LOCATION_HEADER, location);
+            url = location;
+            response = client.send(|| client.http.get(url.clone())).await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let metadata = response.text().await?;
+        serde_json::from_str::<YggdrasilMetadata>(&metadata)?;
+
+        client.api_root = with_trailing_slash(url);
+        client.metadata = Some(metadata);
+        Ok(client)
+    }
+
+    /// API root used for all requests
+    pub fn api_root(&self) -> &Url {
+        &self.api_root
+    }
+
+    /// Server metadata, if it was fetched during discovery
+    pub fn metadata(&self) -> Option<YggdrasilMetadata> {
+        serde_json::from_str(self.metadata.as_deref()?).ok()
+    }
+
+    /// Send a request built by `build`, retrying transient failures
+    async fn send<F>(&self, build: F) -> Result<Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        retry::send_with_retry(&self.config.retry, Idempotency::Idempotent, build).await
+    }
+
+    /// Send a request that invalidates the token it carries, without retrying timeouts
+    async fn send_single_use<F>(&self, build: F) -> Result<Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        retry::send_with_retry(&self.config.retry, Idempotency::SingleUse, build).await
+    }
+
+    fn endpoint(&self, path: &str) -> Result<Url> {
+        Ok(self.api_root.join(path)?)
+    }
+
+    /// Authenticate with username (or email) and password
+    ///
+    /// If the server doesn't select a profile, the first available one is bound.
+    #[instrument(skip(self, password))]
+    pub async fn authenticate(&self, username: &str, password: &str) -> Result<YggdrasilSession> {
+        let request = YggdrasilAuthenticateRequest {
+            agent: YggdrasilAgent::default(),
+            username: username.to_string(),
+            password: password.to_string(),
+            client_token: generate_client_token()?,
+            request_user: true,
+        };
+        let url = self.endpoint("authserver/authenticate")?;
+
+        debug!("Authenticating with Yggdrasil server");
+        let response = self
+            .send(|| self.http.post(url.clone()).json(&request))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let auth: YggdrasilAuthResponse = response.json().await?;
+
+        match auth.selected_profile.clone() {
+            Some(profile) => Ok(self.session_from_response(auth, profile)),
+            None => {
+                let profile = auth
+                    .available_profiles
+                    .first()
+                    .cloned()
+                    .ok_or(RcAuthError::MinecraftProfileNotFound)?;
+                debug!("No profile selected, binding {}", profile.name);
+                self.refresh_tokens(&auth.access_token, &auth.client_token, Some(profile))
+                    .await
+            }
+        }
+    }
+
+    /// Refresh a session, invalidating its previous access token
+    #[instrument(skip(self, session))]
+    pub async fn refresh(&self, session: &YggdrasilSession) -> Result<YggdrasilSession> {
+        self.refresh_tokens(&session.access_token, &session.client_token, None)
+            .await
+    }
+
+    async fn refresh_tokens(
+        &self,
+        access_token: &str,
+        client_token: &str,
+        selected_profile: Option<YggdrasilProfile>,
+    ) -> Result<YggdrasilSession> {
+        let request = YggdrasilRefreshRequest {
+            access_token: access_token.to_string(),
+            client_token: client_token.to_string(),
+            selected_profile,
+            request_user: true,
+        };
+        let url = self.endpoint("authserver/refresh")?;
+
+        debug!("Refreshing Yggdrasil session");
+        let response = self
+            .send_single_use(|| self.http.post(url.clone()).json(&request))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        let auth: YggdrasilAuthResponse = response.json().await?;
+        let profile = auth
+            .selected_profile
+            .clone()
+            .ok_or(RcAuthError::MinecraftProfileNotFound)?;
+        Ok(self.session_from_response(auth, profile))
+    }
+
+    /// Check whether the session's access token is still valid
+    #[instrument(skip(self, session))]
+    pub async fn validate(&self, session: &YggdrasilSession) -> Result<bool> {
+        let request = YggdrasilTokenRequest {
+            access_token: session.access_token.clone(),
+            client_token: session.client_token.clone(),
+        };
+        let url = self.endpoint("authserver/validate")?;
+
+        let response = self
+            .send(|| self.http.post(url.clone()).json(&request))
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::FORBIDDEN => Ok(false),
+            _ => Err(error_from_response(response).await),
+        }
+    }
+
+    /// Invalidate the session's access token
+    #[instrument(skip(self, session))]
+    pub async fn invalidate(&self, session: &YggdrasilSession) -> Result<()> {
+        let request = YggdrasilTokenRequest {
+            access_token: session.access_token.clone(),
+            client_token: session.client_token.clone(),
+        };
+        let url = self.endpoint("authserver/invalidate")?;
+
+        let response = self
+            .send(|| self.http.post(url.clone()).json(&request))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        Ok(())
+    }
+
+    /// Invalidate all access tokens of an account
+    #[instrument(skip(self, password))]
+    pub async fn signout(&self, username: &str, password: &str) -> Result<()> {
+        let request = YggdrasilSignoutRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        let url = self.endpoint("authserver/signout")?;
+
+        let response = self
+            .send(|| self.http.post(url.clone()).json(&request))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+
+        Ok(())
+    }
+
+    fn session_from_response(
+        &self,
+        auth: YggdrasilAuthResponse,
+        profile: YggdrasilProfile,
+    ) -> YggdrasilSession {
+        YggdrasilSession {
+            api_root: self.api_root.to_string(),
+            metadata: self.metadata.clone(),
+            access_token: auth.access_token,
+            client_token: auth.client_token,
+            profile,
+            user_id: auth.user.map(|u| u.id),
+        }
+    }
+}
+
+/// Convert a Yggdrasil error response into an error
+async fn error_from_response(response: Response) -> RcAuthError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+
+    match serde_json::from_str::<YggdrasilErrorResponse>(&body) {
+        Ok(error) => RcAuthError::Yggdrasil {
+            message: error.error_message.unwrap_or_default(),
+            error: error.error,
+        },
+        Err(_) => RcAuthError::Http {
+            status,
+            body_snippet: body.chars().take(200).collect(),
+        },
+    }
+}
+
+/// Generate a random client token (32 hex characters)
+fn generate_client_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| RcAuthError::Crypto(format!("Failed to generate client token: {}", e)))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{Account, UserType};
+    use crate::store::{MemoryTokenStore, TokenStore};
+    use serde_json::json;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn metadata() -> serde_json::Value {
+        json!({
+            "meta": { "serverName": "Team Skins", "implementationName": "test" },
+            "skinDomains": ["skins.example.com"],
+            "signaturePublickey": "-----BEGIN PUBLIC KEY-----\n...\n-----END PUBLIC KEY-----"
+        })
+    }
+
+    async fn discovered_client(server: &MockServer) -> YggdrasilClient {
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(200).insert_header(API_LOCATION_HEADER, "/api/"))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(metadata()))
+            .mount(server)
+            .await;
+
+        YggdrasilClient::discover(RcAuthConfig::default(), &server.uri())
+            .await
+            .unwrap()
+    }
+
+    fn auth_response(access_token: &str, selected: bool) -> serde_json::Value {
+        let profile = json!({ "id": "0123456789abcdef0123456789abcdef", "name": "Alex" });
+        json!({
+            "accessToken": access_token,
+            "clientToken": "client-token",
+            "availableProfiles": [profile],
+            "selectedProfile": if selected { profile } else { serde_json::Value::Null },
+            "user": { "id": "user-id", "properties": [] }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_discover_follows_api_location() {
+        let server = MockServer::start().await;
+        let client = discovered_client(&server).await;
+
+        assert_eq!(client.api_root().as_str(), format!("{}/api/", server.uri()));
+        assert_eq!(
+            client.metadata().unwrap().meta.server_name.as_deref(),
+            Some("Team Skins")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_and_store() {
+        let server = MockServer::start().await;
+        let client = discovered_client(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/authenticate"))
+            .and(body_partial_json(json!({
+                "agent": { "name": "Minecraft", "version": 1 },
+                "username": "alex@example.com",
+                "password": "hunter2"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(auth_response("token-1", true)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let session = client
+            .authenticate("alex@example.com", "hunter2")
+            .await
+            .unwrap();
+        assert_eq!(session.access_token, "token-1");
+        assert_eq!(session.profile.name, "Alex");
+        assert_eq!(session.user_id.as_deref(), Some("user-id"));
+
+        let store = MemoryTokenStore::new();
+        let account = Account::from(session.clone());
+        store
+            .save_account(account.account_key(), &account)
+            .await
+            .unwrap();
+
+        let loaded = store
+            .load_account("0123456789abcdef0123456789abcdef")
+            .await
+            .unwrap();
+        assert_eq!(loaded.user_type(), UserType::Mojang);
+        assert_eq!(loaded.access_token(), Some("token-1"));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_binds_first_profile() {
+        let server = MockServer::start().await;
+        let client = discovered_client(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/authenticate"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(auth_response("token-1", false)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/refresh"))
+            .and(body_partial_json(json!({
+                "accessToken": "token-1",
+                "selectedProfile": { "name": "Alex" }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(auth_response("token-2", true)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let session = client.authenticate("alex", "hunter2").await.unwrap();
+        assert_eq!(session.access_token, "token-2");
+        assert_eq!(session.profile.name, "Alex");
+    }
+
+    #[tokio::test]
+    async fn test_validate_refresh_invalidate_signout() {
+        let server = MockServer::start().await;
+        let client = discovered_client(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/authenticate"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(auth_response("token-1", true)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/validate"))
+            .and(body_partial_json(json!({ "accessToken": "token-1" })))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                "error": "ForbiddenOperationException",
+                "errorMessage": "Invalid token."
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/validate"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/refresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(auth_response("token-2", true)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/invalidate"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/signout"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let session = client.authenticate("alex", "hunter2").await.unwrap();
+        assert!(!client.validate(&session).await.unwrap());
+
+        let session = client.refresh(&session).await.unwrap();
+        assert_eq!(session.access_token, "token-2");
+        assert!(client.validate(&session).await.unwrap());
+
+        client.invalidate(&session).await.unwrap();
+        client.signout("alex", "hunter2").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_credentials() {
+        let server = MockServer::start().await;
+        let client = discovered_client(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/authserver/authenticate"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+                "error": "ForbiddenOperationException",
+                "errorMessage": "Invalid credentials. Invalid username or password."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let result = client.authenticate("alex", "wrong").await;
+        assert!(matches!(
+            result,
+            Err(RcAuthError::Yggdrasil { ref error, .. }) if error == "ForbiddenOperationException"
+        ));
+    }
+
+    #[test]
+    fn test_jvm_args() {
+        let session = YggdrasilSession {
+            api_root: "https://skins.example.com/api/yggdrasil/".to_string(),
+            metadata: Some("{}".to_string()),
+            access_token: "token".to_string(),
+            client_token: "client".to_string(),
+            profile: YggdrasilProfile {
+                id: "id".to_string(),
+                name: "Alex".to_string(),
+            },
+            user_id: None,
+        };
+
+        let args = session.jvm_args(Path::new("/opt/authlib-injector.jar"));
+        assert_eq!(
+            args,
+            vec![
+                "-javaagent:/opt/authlib-injector.jar=https://skins.example.com/api/yggdrasil/"
+                    .to_string(),
+                "-Dauthlibinjector.yggdrasil.prefetched=e30=".to_string(),
+            ]
+        );
+    }
+}